@@ -17,6 +17,36 @@ impl TreeGrid {
     fn height(&self) -> usize {
         self.trees.len()
     }
+
+    fn path(&self, row_step: usize, col_step: usize) -> impl Iterator<Item = (usize, usize)> {
+        (0..self.height())
+            .step_by(row_step)
+            .enumerate()
+            .map(move |(step, row)| (row, step * col_step))
+    }
+
+    fn render_path(&self, row_step: usize, col_step: usize) -> String {
+        let visited: HashSet<(usize, usize)> = self.path(row_step, col_step).collect();
+        let furthest_col = visited.iter().map(|&(_, col)| col).max().unwrap_or(0);
+        // repeat the whole pattern as many times as it takes to fit the path
+        let repeats = furthest_col / self.width + 1;
+
+        let mut result = String::new();
+        for row in 0..self.height() {
+            for col in 0..self.width * repeats {
+                let tree = self.has_tree_at(row, col);
+                let cell = match (visited.contains(&(row, col)), tree) {
+                    (true, true) => 'X',
+                    (true, false) => 'O',
+                    (false, true) => '#',
+                    (false, false) => '.',
+                };
+                result.push(cell);
+            }
+            result.push('\n');
+        }
+        result
+    }
 }
 
 fn parse_input(input: &'static str) -> TreeGrid {
@@ -39,15 +69,10 @@ fn parse_input(input: &'static str) -> TreeGrid {
 }
 
 fn find_trees(tree_grid: &TreeGrid, row_step: usize, col_step: usize) -> usize {
-    let mut count = 0;
-    let mut col = 0;
-    for row in (0..tree_grid.height()).step_by(row_step) {
-        if tree_grid.has_tree_at(row, col) {
-            count += 1;
-        }
-        col += col_step;
-    }
-    count
+    tree_grid
+        .path(row_step, col_step)
+        .filter(|&(row, col)| tree_grid.has_tree_at(row, col))
+        .count()
 }
 
 fn multiply_paths(tree_grid: &TreeGrid) -> usize {
@@ -72,9 +97,29 @@ pub fn p2() -> usize {
     multiply_paths(&tree_grid)
 }
 
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 3 path <right> <down>";
+    match args {
+        [command, right, down] if command == "path" => {
+            let (col_step, row_step) = match (right.parse(), down.parse()) {
+                (Ok(right), Ok(down)) if down > 0 => (right, down),
+                _ => return usage.to_string(),
+            };
+            let tree_grid = parse_input(INPUT);
+            format!(
+                "{}\nTrees hit: {}",
+                tree_grid.render_path(row_step, col_step),
+                find_trees(&tree_grid, row_step, col_step)
+            )
+        }
+        _ => usage.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &'static str = r#"..##.......
 #...#...#..
@@ -119,4 +164,27 @@ mod test {
 
         assert_eq!(9354744432, multiplied);
     }
+
+    #[test]
+    fn render_path_example() {
+        let tree_grid = parse_input(EXAMPLE);
+        let rendered = tree_grid.render_path(1, 3);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(11, lines.len());
+        assert_eq!("O.##.........##.........##.......", lines[0]);
+        assert_eq!("#..O#...#..#...#...#..#...#...#..", lines[1]);
+        assert_eq!(".#....X..#..#....#..#..#....#..#.", lines[2]);
+        assert_eq!(7, rendered.matches('X').count());
+        assert_eq!(4, rendered.matches('O').count());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["path", "3"])).starts_with("Usage:"));
+        assert!(tools(&args(&["path", "3", "0"])).starts_with("Usage:"));
+        assert!(tools(&args(&["path", "right", "1"])).starts_with("Usage:"));
+        assert!(tools(&args(&["path", "3", "1"])).ends_with("Trees hit: 292"));
+    }
 }
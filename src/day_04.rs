@@ -1,107 +1,425 @@
-use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use std::{collections::HashSet, fmt, fs, str::FromStr};
 
 static INPUT: &'static str = include_str!("assets/day_04_input.txt");
+static DEFAULT_SCHEMA: &str = include_str!("assets/day_04_schema.txt");
 
-fn passport_from_str(passport_str: &str) -> HashMap<&str, &str> {
-    passport_str
-        .trim()
-        .split_whitespace()
-        .filter_map(|key_value_pair| {
-            let tokens: Vec<&str> = key_value_pair.split(':').collect();
-            match tokens.as_slice() {
-                [key, value] => Some((*key, *value)),
-                _ => None,
-            }
-        })
+// Passports are separated by lines with nothing but whitespace on them. Each comes with the
+// line it starts on and its lines joined into one.
+fn split_passports(passports_str: &str) -> Vec<(usize, String)> {
+    let mut records: Vec<(usize, Vec<&str>)> = Vec::new();
+    let mut in_record = false;
+    for (line_index, line) in passports_str.lines().enumerate() {
+        if line.trim().is_empty() {
+            in_record = false;
+            continue;
+        }
+        if !in_record {
+            records.push((line_index + 1, Vec::new()));
+            in_record = true;
+        }
+        records.last_mut().unwrap().1.push(line);
+    }
+
+    records
+        .into_iter()
+        .map(|(line, lines)| (line, lines.join(" ")))
         .collect()
 }
 
-fn has_required_fields(
-    passport_fields: HashMap<&str, &str>,
-    required_fields: &HashSet<&'static str>,
-) -> bool {
-    required_fields
-        .iter()
-        .all(|field| passport_fields.get(field).is_some())
+#[derive(Debug)]
+enum Constraint {
+    IntRange(i64, i64),
+    Units(Vec<(String, i64, i64)>),
+    Pattern(Regex),
+    Charset(Vec<(char, char)>),
+    OneOf(Vec<String>),
+    Length(usize),
 }
 
-fn count_valid_p1(passports_str: &'static str) -> usize {
-    let required_fields = hashset! { "byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid" };
-    let passports = passports_str.split("\n\n");
-    passports
-        .filter(|passport| has_required_fields(passport_from_str(passport), &required_fields))
-        .count()
+fn parse_range(range_str: &str) -> Result<(i64, i64), String> {
+    let tokens: Vec<&str> = range_str.split("..=").collect();
+    match tokens.as_slice() {
+        [min, max] => match (min.parse(), max.parse()) {
+            (Ok(min), Ok(max)) => Ok((min, max)),
+            _ => Err(format!("invalid range bounds '{}'", range_str)),
+        },
+        _ => Err(format!("expected a range like 1..=10, got '{}'", range_str)),
+    }
+}
+
+fn parse_charset(charset_str: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = charset_str.chars().collect();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 2 < chars.len() && chars[i + 1] == '-' {
+            ranges.push((chars[i], chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((chars[i], chars[i]));
+            i += 1;
+        }
+    }
+    ranges
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, args) = match s.find(' ') {
+            Some(index) => (&s[..index], s[index..].trim()),
+            None => (s, ""),
+        };
+        if args.is_empty() {
+            return Err(format!("constraint '{}' is missing its arguments", kind));
+        }
+
+        match kind {
+            "int" => {
+                let (min, max) = parse_range(args)?;
+                Ok(Constraint::IntRange(min, max))
+            }
+            "units" => {
+                let tokens: Vec<&str> = args.split_whitespace().collect();
+                if !tokens.len().is_multiple_of(2) {
+                    return Err(format!("expected <unit> <range> pairs, got '{}'", args));
+                }
+                let units = tokens
+                    .chunks(2)
+                    .map(|pair| {
+                        let (min, max) = parse_range(pair[1])?;
+                        Ok((pair[0].to_string(), min, max))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(Constraint::Units(units))
+            }
+            "regex" => Regex::new(args)
+                .map(Constraint::Pattern)
+                .map_err(|err| format!("invalid regex '{}': {}", args, err)),
+            "charset" => Ok(Constraint::Charset(parse_charset(args))),
+            "enum" => Ok(Constraint::OneOf(
+                args.split_whitespace().map(str::to_string).collect(),
+            )),
+            "length" => args
+                .parse()
+                .map(Constraint::Length)
+                .map_err(|_| format!("invalid length '{}'", args)),
+            _ => Err(format!("unknown constraint '{}'", kind)),
+        }
+    }
+}
+
+impl Constraint {
+    fn check(&self, value: &str) -> bool {
+        match self {
+            Constraint::IntRange(min, max) => value
+                .parse::<i64>()
+                .is_ok_and(|value| (*min..=*max).contains(&value)),
+            Constraint::Units(units) => units.iter().any(|(unit, min, max)| {
+                value.strip_suffix(unit.as_str()).is_some_and(|num| {
+                    num.parse::<i64>()
+                        .is_ok_and(|num| (*min..=*max).contains(&num))
+                })
+            }),
+            Constraint::Pattern(regex) => regex.is_match(value),
+            Constraint::Charset(ranges) => value
+                .chars()
+                .all(|c| ranges.iter().any(|&(low, high)| low <= c && c <= high)),
+            Constraint::OneOf(options) => options.iter().any(|option| option == value),
+            Constraint::Length(length) => value.chars().count() == *length,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::IntRange(min, max) => write!(f, "int {}..={}", min, max),
+            Constraint::Units(units) => {
+                write!(f, "units")?;
+                for (unit, min, max) in units {
+                    write!(f, " {} {}..={}", unit, min, max)?;
+                }
+                Ok(())
+            }
+            Constraint::Pattern(regex) => write!(f, "regex {}", regex.as_str()),
+            Constraint::Charset(ranges) => {
+                write!(f, "charset ")?;
+                for (low, high) in ranges {
+                    if low == high {
+                        write!(f, "{}", low)?;
+                    } else {
+                        write!(f, "{}-{}", low, high)?;
+                    }
+                }
+                Ok(())
+            }
+            Constraint::OneOf(options) => write!(f, "enum {}", options.join(" ")),
+            Constraint::Length(length) => write!(f, "length {}", length),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FieldRule {
+    name: String,
+    required: bool,
+    constraints: Vec<Constraint>,
 }
 
-fn validate_fields(passport_fields: HashMap<&str, &str>) -> bool {
-    vec![
-        passport_fields.get("byr").map(|value| {
-            let value: i32 = value.parse().unwrap();
-            value >= 1920 && value <= 2002
-        }),
-        passport_fields.get("iyr").map(|value| {
-            let value: i32 = value.parse().unwrap();
-            value >= 2010 && value <= 2020
-        }),
-        passport_fields.get("eyr").map(|value| {
-            let value: i32 = value.parse().unwrap();
-            value >= 2020 && value <= 2030
-        }),
-        passport_fields.get("hgt").map(|value| {
-            if value.len() <= 2 {
-                return false;
+#[derive(Debug)]
+struct Schema {
+    fields: Vec<FieldRule>,
+}
+
+impl FromStr for Schema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields: Vec<FieldRule> = Vec::new();
+        for (line_index, line) in s.lines().enumerate() {
+            let line_number = line_index + 1;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with(char::is_whitespace) {
+                let field = fields
+                    .last_mut()
+                    .ok_or_else(|| format!("line {}: constraint before any field", line_number))?;
+                let constraint = line
+                    .parse()
+                    .map_err(|err| format!("line {}: {}", line_number, err))?;
+                field.constraints.push(constraint);
+                continue;
             }
-            let (num, unit) = value.split_at(value.len() - 2);
-            match unit {
-                "cm" => {
-                    let height: i32 = num.parse().unwrap_or(-1);
-                    height >= 150 && height <= 193
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let required = match tokens.as_slice() {
+                [_, "required"] => true,
+                [_, "optional"] => false,
+                _ => {
+                    return Err(format!(
+                        "line {}: expected '<field> <required|optional>'",
+                        line_number
+                    ))
                 }
-                "in" => {
-                    let height: i32 = num.parse().unwrap_or(-1);
-                    height >= 59 && height <= 76
+            };
+            if fields.iter().any(|field| field.name == tokens[0]) {
+                return Err(format!(
+                    "line {}: field '{}' declared twice",
+                    line_number, tokens[0]
+                ));
+            }
+            fields.push(FieldRule {
+                name: tokens[0].to_string(),
+                required,
+                constraints: Vec::new(),
+            });
+        }
+
+        Ok(Self { fields })
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        DEFAULT_SCHEMA.parse().unwrap()
+    }
+}
+
+impl Schema {
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        contents.parse()
+    }
+
+    // Both checks go through `report`, so a passport is only valid when it has nothing to
+    // report. Complete passports may still have values that break their constraints.
+    fn is_complete(&self, passport_str: &str) -> bool {
+        self.report(passport_str)
+            .iter()
+            .all(|error| matches!(error, FieldError::Invalid { .. }))
+    }
+
+    fn validate(&self, passport_str: &str) -> bool {
+        self.report(passport_str).is_empty()
+    }
+
+    fn report(&self, passport_str: &str) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        for key_value_pair in passport_str.split_whitespace() {
+            let (key, value) = match key_value_pair.split_once(':') {
+                Some(pair) => pair,
+                None => {
+                    errors.push(FieldError::Malformed(key_value_pair.to_string()));
+                    continue;
                 }
-                _ => false,
+            };
+            if !seen.insert(key) {
+                errors.push(FieldError::Duplicate(key.to_string()));
+                continue;
+            }
+
+            match self.fields.iter().find(|field| field.name == key) {
+                Some(field) => errors.extend(
+                    field
+                        .constraints
+                        .iter()
+                        .filter(|constraint| !constraint.check(value))
+                        .map(|constraint| FieldError::Invalid {
+                            field: key.to_string(),
+                            value: value.to_string(),
+                            constraint: constraint.to_string(),
+                        }),
+                ),
+                None => errors.push(FieldError::Unknown(key.to_string())),
             }
-        }),
-        passport_fields
-            .get("hcl")
-            .map(|value| value.len() == 7 && i64::from_str_radix(&value[1..], 16).is_ok()),
-        passport_fields.get("ecl").map(|&value| {
-            value == "amb"
-                || value == "blu"
-                || value == "brn"
-                || value == "gry"
-                || value == "grn"
-                || value == "hzl"
-                || value == "oth"
-        }),
-        passport_fields
-            .get("pid")
-            .map(|value| value.len() == 9 && value.parse::<i32>().is_ok()),
-    ]
-    .iter()
-    .all(|&is_valid| is_valid == Some(true))
-}
-
-fn count_valid_p2(passports_str: &'static str) -> usize {
-    let passports = passports_str.split("\n\n");
+        }
+
+        for field in self.fields.iter().filter(|field| field.required) {
+            if !seen.contains(field.name.as_str()) {
+                errors.push(FieldError::Missing(field.name.clone()));
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldError {
+    Missing(String),
+    Unknown(String),
+    Duplicate(String),
+    Malformed(String),
+    Invalid {
+        field: String,
+        value: String,
+        constraint: String,
+    },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "missing required field '{}'", field),
+            FieldError::Unknown(field) => write!(f, "unknown field '{}'", field),
+            FieldError::Duplicate(field) => write!(f, "duplicated field '{}'", field),
+            FieldError::Malformed(token) => write!(f, "malformed entry '{}'", token),
+            FieldError::Invalid {
+                field,
+                value,
+                constraint,
+            } => write!(
+                f,
+                "field '{}' value '{}' fails '{}'",
+                field, value, constraint
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PassportReport {
+    index: usize,
+    line: usize,
+    errors: Vec<FieldError>,
+}
+
+impl fmt::Display for PassportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Passport {} (line {}):", self.index, self.line)?;
+        for error in &self.errors {
+            write!(f, "\n\t{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+fn report_passports(passports_str: &str, schema: &Schema) -> Vec<PassportReport> {
+    split_passports(passports_str)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (line, passport))| PassportReport {
+            index,
+            line,
+            errors: schema.report(&passport),
+        })
+        .collect()
+}
+
+fn count_valid_p1(passports_str: &str, schema: &Schema) -> usize {
+    let passports = split_passports(passports_str);
+    passports
+        .iter()
+        .filter(|(_, passport)| schema.is_complete(passport))
+        .count()
+}
+
+fn count_valid_p2(passports_str: &str, schema: &Schema) -> usize {
+    let passports = split_passports(passports_str);
     passports
-        .filter(|passport| validate_fields(passport_from_str(passport)))
+        .iter()
+        .filter(|(_, passport)| schema.validate(passport))
         .count()
 }
 
 pub fn p1() -> usize {
-    count_valid_p1(INPUT)
+    count_valid_p1(INPUT, &Schema::default())
 }
 
 pub fn p2() -> usize {
-    count_valid_p2(INPUT)
+    count_valid_p2(INPUT, &Schema::default())
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 4 <validate|report> <passports> [schema]";
+    let (command, passports_path, schema) = match args {
+        [command, passports] => (command, passports, Ok(Schema::default())),
+        [command, passports, schema] => (command, passports, Schema::from_file(schema)),
+        _ => return usage.to_string(),
+    };
+    let schema = match schema {
+        Ok(schema) => schema,
+        Err(err) => return format!("Invalid schema: {}", err),
+    };
+    let passports = match fs::read_to_string(passports_path) {
+        Ok(passports) => passports,
+        Err(err) => return format!("{}: {}", passports_path, err),
+    };
+
+    match command.as_str() {
+        "validate" => format!(
+            "Complete: {}\nValid: {}",
+            count_valid_p1(&passports, &schema),
+            count_valid_p2(&passports, &schema)
+        ),
+        "report" => {
+            let reports = report_passports(&passports, &schema);
+            let failing: Vec<String> = reports
+                .iter()
+                .filter(|report| !report.errors.is_empty())
+                .map(|report| report.to_string())
+                .collect();
+            format!(
+                "{}\n\n{} of {} passports have errors",
+                failing.join("\n"),
+                failing.len(),
+                reports.len()
+            )
+        }
+        _ => usage.to_string(),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE_P1: &'static str = r#"ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
 byr:1937 iyr:2017 cid:147 hgt:183cm
@@ -146,20 +464,161 @@ iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719"#;
 
     #[test]
     fn p1_example() {
-        assert_eq!(2, count_valid_p1(EXAMPLE_P1));
+        assert_eq!(2, count_valid_p1(EXAMPLE_P1, &Schema::default()));
     }
 
     #[test]
     fn p1_correct_answer() {
-        assert_eq!(182, count_valid_p1(INPUT));
+        assert_eq!(182, count_valid_p1(INPUT, &Schema::default()));
     }
 
     #[test]
     fn p2_example() {
-        assert_eq!(0, count_valid_p2(EXAMPLE_P2_INVALID));
-        assert_eq!(4, count_valid_p2(EXAMPLE_P2_VALID));
+        let schema = Schema::default();
+        assert_eq!(0, count_valid_p2(EXAMPLE_P2_INVALID, &schema));
+        assert_eq!(4, count_valid_p2(EXAMPLE_P2_VALID, &schema));
     }
 
-    // #[test]
-    // fn p2_correct_answer() {}
+    #[test]
+    fn p2_correct_answer() {
+        assert_eq!(109, count_valid_p2(INPUT, &Schema::default()));
+    }
+
+    #[test]
+    fn custom_schema() {
+        let schema: Schema = r#"
+name required
+    charset A-Za-z
+    regex ^[A-Z]
+age optional
+    int 0..=150
+"#
+        .parse()
+        .unwrap();
+        let passports = "name:Ada age:36\n\nname:bob\n\nage:200 name:Cy\n\nage:3";
+
+        assert_eq!(3, count_valid_p1(passports, &schema));
+        assert_eq!(1, count_valid_p2(passports, &schema));
+    }
+
+    #[test]
+    fn report_example() {
+        let reports = report_passports(EXAMPLE_P2_INVALID, &Schema::default());
+
+        assert_eq!(4, reports.len());
+        assert_eq!(
+            vec![1, 4, 8, 11],
+            reports.iter().map(|report| report.line).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                FieldError::Invalid {
+                    field: "eyr".to_string(),
+                    value: "1972".to_string(),
+                    constraint: "int 2020..=2030".to_string(),
+                },
+                FieldError::Invalid {
+                    field: "hgt".to_string(),
+                    value: "170".to_string(),
+                    constraint: "units cm 150..=193 in 59..=76".to_string(),
+                },
+                FieldError::Invalid {
+                    field: "pid".to_string(),
+                    value: "186cm".to_string(),
+                    constraint: "length 9".to_string(),
+                },
+                FieldError::Invalid {
+                    field: "pid".to_string(),
+                    value: "186cm".to_string(),
+                    constraint: "charset 0-9".to_string(),
+                },
+            ],
+            reports[0].errors
+        );
+        assert!(report_passports(EXAMPLE_P2_VALID, &Schema::default())
+            .iter()
+            .all(|report| report.errors.is_empty()));
+    }
+
+    #[test]
+    fn report_structural_errors() {
+        let reports = report_passports(
+            "\n\nbyr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc\necl:blu pid:000000001 byr:1981 foo:bar baz",
+            &Schema::default(),
+        );
+
+        assert_eq!(1, reports.len());
+        assert_eq!(3, reports[0].line);
+        assert_eq!(
+            vec![
+                FieldError::Duplicate("byr".to_string()),
+                FieldError::Unknown("foo".to_string()),
+                FieldError::Malformed("baz".to_string()),
+            ],
+            reports[0].errors
+        );
+        assert_eq!(
+            vec![FieldError::Missing("ecl".to_string())],
+            Schema::default()
+                .report("byr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc pid:000000001")
+        );
+    }
+
+    #[test]
+    fn counts_agree_with_report() {
+        let passports = "byr:1980 iyr:2015 eyr:2025 hgt:180cm\n  \t\nhcl:#123abc ecl:blu pid:000000001\n\nbyr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc ecl:blu pid:000000001 byr:1700";
+        let schema = Schema::default();
+        let reports = report_passports(passports, &schema);
+
+        assert_eq!(3, reports.len());
+        assert_eq!(
+            vec![FieldError::Duplicate("byr".to_string())],
+            reports[2].errors
+        );
+        assert_eq!(0, count_valid_p1(passports, &schema));
+        assert_eq!(0, count_valid_p2(passports, &schema));
+        assert_eq!(
+            vec![1, 3, 5],
+            reports.iter().map(|report| report.line).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unknown_and_malformed_fields() {
+        let passports = "byr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc ecl:blu pid:000000001 foo:bar\n\nbyr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc ecl:blu pid:000000001 stray\n\nbyr:1980 iyr:2015 eyr:2025 hgt:180cm hcl:#123abc ecl:blu pid:000000001 cid:7";
+        let schema = Schema::default();
+        let failing = report_passports(passports, &schema)
+            .iter()
+            .filter(|report| !report.errors.is_empty())
+            .count();
+
+        assert_eq!(2, failing);
+        assert_eq!(1, count_valid_p1(passports, &schema));
+        assert_eq!(1, count_valid_p2(passports, &schema));
+    }
+
+    #[test]
+    fn invalid_schema() {
+        assert!("byr sometimes".parse::<Schema>().is_err());
+        assert!("    int 1..=2".parse::<Schema>().is_err());
+        assert!("byr required\n    int 1-2".parse::<Schema>().is_err());
+        assert!("byr required\n    bogus 1".parse::<Schema>().is_err());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        let passports = "src/assets/day_04_input.txt";
+
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["validate"])).starts_with("Usage:"));
+        assert!(tools(&args(&["stamp", passports])).starts_with("Usage:"));
+        assert_eq!(
+            "Complete: 182\nValid: 109",
+            tools(&args(&["validate", passports]))
+        );
+        assert!(tools(&args(&["report", passports])).ends_with("142 of 251 passports have errors"));
+        assert!(tools(&args(&["validate", "no/such/file"])).starts_with("no/such/file: "));
+        assert!(tools(&args(&["validate", passports, "no/such/file"]))
+            .starts_with("Invalid schema: no/such/file: "));
+    }
 }
@@ -1,47 +1,335 @@
+use std::{collections::BTreeMap, fmt, fs, str::FromStr};
+
 static INPUT: &'static str = include_str!("assets/day_05_input.txt");
 
-fn get_seat_id(boarding_pass: &str) -> usize {
-    let mut id: usize = 0;
-    for c in boarding_pass.chars() {
-        if c == 'B' || c == 'R' {
-            id += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Seat {
+    row: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PlaneLayout {
+    row_bits: usize,
+    column_bits: usize,
+    // (lower half, upper half)
+    row_letters: (char, char),
+    column_letters: (char, char),
+}
+
+impl Default for PlaneLayout {
+    fn default() -> Self {
+        Self {
+            row_bits: 7,
+            column_bits: 3,
+            row_letters: ('F', 'B'),
+            column_letters: ('L', 'R'),
+        }
+    }
+}
+
+fn parse_layout_part(part: &str) -> Result<(usize, (char, char)), String> {
+    let split = part
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing letters in '{}'", part))?;
+    let (bits, letters) = part.split_at(split);
+    let bits: usize = bits
+        .parse()
+        .map_err(|_| format!("invalid bit count in '{}'", part))?;
+    let letters: Vec<char> = letters.chars().collect();
+    match letters.as_slice() {
+        [lower, upper] if lower != upper => Ok((bits, (*lower, *upper))),
+        _ => Err(format!("expected two distinct letters in '{}'", part)),
+    }
+}
+
+// Layouts are written as `<row bits><lower><upper>/<column bits><lower><upper>`, e.g. `7FB/3LR`
+impl FromStr for PlaneLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, columns) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected <rows>/<columns>, got '{}'", s))?;
+        let (row_bits, row_letters) = parse_layout_part(rows)?;
+        let (column_bits, column_letters) = parse_layout_part(columns)?;
+        if row_bits + column_bits >= usize::BITS as usize {
+            return Err(format!("layout '{}' has too many bits", s));
+        }
+
+        Ok(Self {
+            row_bits,
+            column_bits,
+            row_letters,
+            column_letters,
+        })
+    }
+}
+
+fn decode_bits(code: &str, (lower, upper): (char, char)) -> Result<usize, String> {
+    code.chars().try_fold(0, |value, c| match c {
+        c if c == lower => Ok(value << 1),
+        c if c == upper => Ok((value << 1) | 1),
+        _ => Err(format!(
+            "unexpected letter '{}', expected '{}' or '{}'",
+            c, lower, upper
+        )),
+    })
+}
+
+fn encode_bits(value: usize, bits: usize, (lower, upper): (char, char)) -> String {
+    (0..bits)
+        .rev()
+        .map(|bit| {
+            if value & (1 << bit) == 0 {
+                lower
+            } else {
+                upper
+            }
+        })
+        .collect()
+}
+
+impl PlaneLayout {
+    fn rows(&self) -> usize {
+        1 << self.row_bits
+    }
+
+    fn columns(&self) -> usize {
+        1 << self.column_bits
+    }
+
+    fn seat_id(&self, seat: Seat) -> usize {
+        seat.row * self.columns() + seat.column
+    }
+
+    fn decode(&self, boarding_pass: &str) -> Result<Seat, String> {
+        let length = boarding_pass.chars().count();
+        if length != self.row_bits + self.column_bits {
+            return Err(format!(
+                "boarding pass '{}' has {} letters, expected {}",
+                boarding_pass,
+                length,
+                self.row_bits + self.column_bits
+            ));
+        }
+
+        // only ASCII letters are allowed, so splitting at a byte offset is safe
+        if !boarding_pass.is_ascii() {
+            return Err(format!("boarding pass '{}' is not ASCII", boarding_pass));
+        }
+        let (row_code, column_code) = boarding_pass.split_at(self.row_bits);
+        Ok(Seat {
+            row: decode_bits(row_code, self.row_letters)?,
+            column: decode_bits(column_code, self.column_letters)?,
+        })
+    }
+
+    fn encode(&self, seat: Seat) -> Result<String, String> {
+        if seat.row >= self.rows() || seat.column >= self.columns() {
+            return Err(format!(
+                "seat ({}, {}) is outside a {}x{} plane",
+                seat.row,
+                seat.column,
+                self.rows(),
+                self.columns()
+            ));
         }
-        id <<= 1;
+
+        Ok(format!(
+            "{}{}",
+            encode_bits(seat.row, self.row_bits, self.row_letters),
+            encode_bits(seat.column, self.column_bits, self.column_letters)
+        ))
     }
-    // We didn't actually want the last shift, it's easy enough to just unshift here
-    id >> 1
+}
+
+fn get_seat_id(boarding_pass: &str) -> usize {
+    let layout = PlaneLayout::default();
+    let seat = layout.decode(boarding_pass).expect("invalid boarding pass");
+    layout.seat_id(seat)
 }
 
 fn highest_seat_id(input: &str) -> usize {
     input.lines().map(get_seat_id).max().expect("invalid input")
 }
 
-fn find_missing_seat(input: &str) -> usize {
-    let mut filled_seats: Vec<usize> = input.lines().map(get_seat_id).collect();
-    filled_seats.sort();
+#[derive(Debug)]
+struct SeatMap {
+    layout: PlaneLayout,
+    // number of boarding passes seen for each occupied seat ID; layouts can have far more
+    // seats than could ever be stored one by one
+    passes: BTreeMap<usize, usize>,
+}
+
+impl SeatMap {
+    fn from_passes(input: &str, layout: PlaneLayout) -> Result<Self, String> {
+        let mut passes = BTreeMap::new();
+        for (i, line) in input.lines().enumerate() {
+            let seat = layout
+                .decode(line.trim())
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+            *passes.entry(layout.seat_id(seat)).or_insert(0) += 1;
+        }
+
+        Ok(Self { layout, passes })
+    }
+
+    fn first_occupied(&self) -> Option<usize> {
+        self.passes.keys().next().copied()
+    }
+
+    fn last_occupied(&self) -> Option<usize> {
+        self.passes.keys().next_back().copied()
+    }
+
+    fn is_occupied(&self, id: usize) -> bool {
+        self.passes.contains_key(&id)
+    }
+
+    // Empty seats between the first and last occupied seats; anything outside that range is
+    // treated as missing from the front or back of the plane.
+    fn gaps(&self) -> Vec<usize> {
+        self.passes
+            .keys()
+            .zip(self.passes.keys().skip(1))
+            .flat_map(|(&id, &next)| id + 1..next)
+            .collect()
+    }
+
+    fn duplicates(&self) -> Vec<usize> {
+        self.passes
+            .iter()
+            .filter(|&(_, &count)| count > 1)
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    fn candidates(&self) -> Vec<usize> {
+        self.gaps()
+            .into_iter()
+            .filter(|&id| id > 0 && self.is_occupied(id - 1) && self.is_occupied(id + 1))
+            .collect()
+    }
+}
+
+// Only the rows from the first occupied seat to the last are drawn
+impl fmt::Display for SeatMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, last) = match (self.first_occupied(), self.last_occupied()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(()),
+        };
+        let columns = self.layout.columns();
+        let (first_row, last_row) = (first / columns, last / columns);
+        let row_label_width = last_row.to_string().len();
 
-    // I'm certain there's a better fit for this than `fold` *shrug*
-    let seat_before_missing = filled_seats.iter().fold(filled_seats[0], |prev, &value| {
-        if prev + 1 == value {
-            value
-        } else {
-            prev
+        for row in first_row..=last_row {
+            write!(f, "{:>width$} ", row, width = row_label_width)?;
+            for column in 0..columns {
+                let id = self.layout.seat_id(Seat { row, column });
+                let cell = match self.passes.get(&id) {
+                    _ if id < first || id > last => '-',
+                    None => '.',
+                    Some(1) => '#',
+                    Some(_) => '!',
+                };
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
         }
-    });
-    seat_before_missing + 1
+        Ok(())
+    }
+}
+
+fn find_missing_seat(input: &str) -> Option<usize> {
+    let seat_map = SeatMap::from_passes(input, PlaneLayout::default()).expect("invalid input");
+    seat_map.candidates().first().copied()
 }
 
 pub fn p1() -> usize {
     highest_seat_id(INPUT)
 }
 
-pub fn p2() -> usize {
-    find_missing_seat(INPUT)
+pub fn p2() -> String {
+    find_missing_seat(INPUT).map_or("no seat found".to_string(), |id| id.to_string())
+}
+
+fn format_ids(ids: &[usize]) -> String {
+    if ids.is_empty() {
+        return "none".to_string();
+    }
+    ids.iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = r#"Usage:
+	advent2020 5 decode <pass> [layout]
+	advent2020 5 encode <row> <column> [layout]
+	advent2020 5 map <passes> [layout]
+	advent2020 5 analyze <passes> [layout]
+
+Layouts look like 7FB/3LR"#;
+    let (command, rest) = match args {
+        [command, rest @ ..] => (command.as_str(), rest),
+        _ => return usage.to_string(),
+    };
+    let value_count = match command {
+        "decode" | "map" | "analyze" => 1,
+        "encode" => 2,
+        _ => return usage.to_string(),
+    };
+    if rest.len() != value_count && rest.len() != value_count + 1 {
+        return usage.to_string();
+    }
+    let (values, layout) = (&rest[..value_count], rest.get(value_count));
+    let layout = match layout.map_or(Ok(PlaneLayout::default()), |layout| layout.parse()) {
+        Ok(layout) => layout,
+        Err(err) => return format!("Invalid layout: {}", err),
+    };
+
+    let result = match command {
+        "decode" => layout.decode(&values[0]).map(|seat| {
+            format!(
+                "Row: {}\nColumn: {}\nSeat ID: {}",
+                seat.row,
+                seat.column,
+                layout.seat_id(seat)
+            )
+        }),
+        "encode" => match (values[0].parse(), values[1].parse()) {
+            (Ok(row), Ok(column)) => layout.encode(Seat { row, column }),
+            _ => Err("row and column must be numbers".to_string()),
+        },
+        _ => fs::read_to_string(&values[0])
+            .map_err(|err| format!("{}: {}", values[0], err))
+            .and_then(|input| SeatMap::from_passes(&input, layout))
+            .map(|seat_map| {
+                if command == "map" {
+                    format!(
+                        "{}\n# occupied, ! duplicated, . empty, - missing front/back",
+                        seat_map
+                    )
+                } else {
+                    format!(
+                        "Gaps: {}\nDuplicates: {}\nCandidates: {}",
+                        format_ids(&seat_map.gaps()),
+                        format_ids(&seat_map.duplicates()),
+                        format_ids(&seat_map.candidates())
+                    )
+                }
+            }),
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     #[test]
     fn p1_example() {
@@ -66,11 +354,143 @@ FBFBBFFRLR
 FBFBBFFRRR
 "#;
 
-        assert_eq!(355, find_missing_seat(values));
+        assert_eq!(Some(355), find_missing_seat(values));
     }
 
     #[test]
     fn p2_correct_answer() {
-        assert_eq!(678, find_missing_seat(INPUT));
+        assert_eq!(Some(678), find_missing_seat(INPUT));
+    }
+
+    #[test]
+    fn seat_map_analysis() {
+        let seat_map = SeatMap::from_passes(
+            "FBFBBFFLLL\nFBFBBFFLLR\nFBFBBFFLRL\nFBFBBFFRLL\nFBFBBFFRLR\nFBFBBFFRRR\nFBFBBFFLLR",
+            PlaneLayout::default(),
+        )
+        .unwrap();
+
+        assert_eq!(vec![355, 358], seat_map.gaps());
+        assert_eq!(vec![353], seat_map.duplicates());
+        assert_eq!(vec![355, 358], seat_map.candidates());
+
+        let rendered = seat_map.to_string();
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(vec!["44 #!#.##.#"], rows);
+
+        let seat_map =
+            SeatMap::from_passes("FBFBBFFLLL\nFBFBBFFLRL\nFBFBBFFLRR", PlaneLayout::default())
+                .unwrap();
+        assert_eq!(vec![353], seat_map.gaps());
+        assert_eq!(vec![353], seat_map.candidates());
+    }
+
+    #[test]
+    fn seat_map_without_candidates() {
+        let seat_map =
+            SeatMap::from_passes("FBFBBFFLLL\nFBFBBFFLRR", PlaneLayout::default()).unwrap();
+        assert_eq!(vec![353, 354], seat_map.gaps());
+        assert!(seat_map.candidates().is_empty());
+        assert_eq!(None, find_missing_seat("FBFBBFFLLL\nFBFBBFFLRR"));
+
+        let seat_map = SeatMap::from_passes("", PlaneLayout::default()).unwrap();
+        assert!(seat_map.gaps().is_empty());
+        assert!(seat_map.candidates().is_empty());
+        assert_eq!("", seat_map.to_string());
+    }
+
+    #[test]
+    fn seat_map_huge_layout() {
+        let layout: PlaneLayout = "20FB/20LR".parse().unwrap();
+        let first = Seat {
+            row: 600_000,
+            column: 3,
+        };
+        let last = Seat {
+            row: 600_000,
+            column: 5,
+        };
+        let passes = format!(
+            "{}\n{}",
+            layout.encode(first).unwrap(),
+            layout.encode(last).unwrap()
+        );
+        let seat_map = SeatMap::from_passes(&passes, layout.clone()).unwrap();
+
+        assert_eq!(vec![layout.seat_id(first) + 1], seat_map.gaps());
+        assert_eq!(seat_map.gaps(), seat_map.candidates());
+        assert_eq!(1, seat_map.to_string().lines().count());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let layout = PlaneLayout::default();
+        for pass in INPUT.lines() {
+            let seat = layout.decode(pass).unwrap();
+            assert_eq!(pass, layout.encode(seat).unwrap());
+        }
+        assert_eq!(
+            Seat { row: 44, column: 5 },
+            layout.decode("FBFBBFFRLR").unwrap()
+        );
+        assert_eq!(
+            "FBFBBFFRLR",
+            layout.encode(Seat { row: 44, column: 5 }).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_passes() {
+        let layout = PlaneLayout::default();
+        assert!(layout.decode("FBFBBFFRL").is_err());
+        assert!(layout.decode("FBFBBFFRLRR").is_err());
+        assert!(layout.decode("FBFBBFFRLB").is_err());
+        assert!(layout.decode("FBFBXFFRLR").is_err());
+        assert!(layout.decode("FBFBBFFRLé").is_err());
+        assert!(layout
+            .encode(Seat {
+                row: 128,
+                column: 0
+            })
+            .is_err());
+        assert!(layout.encode(Seat { row: 0, column: 8 }).is_err());
+    }
+
+    #[test]
+    fn custom_layout() {
+        let layout: PlaneLayout = "9UD/4ab".parse().unwrap();
+        let seat = Seat {
+            row: 300,
+            column: 11,
+        };
+        let pass = layout.encode(seat).unwrap();
+
+        assert_eq!("DUUDUDDUUbabb", pass);
+        assert_eq!(seat, layout.decode(&pass).unwrap());
+        assert_eq!(300 * 16 + 11, layout.seat_id(seat));
+        assert!("7FF/3LR".parse::<PlaneLayout>().is_err());
+        assert!("FB/3LR".parse::<PlaneLayout>().is_err());
+        assert!("7FB".parse::<PlaneLayout>().is_err());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["decode"])).starts_with("Usage:"));
+        assert!(tools(&args(&["board", "FBFBBFFRLR"])).starts_with("Usage:"));
+        assert_eq!(
+            "Row: 44\nColumn: 5\nSeat ID: 357",
+            tools(&args(&["decode", "FBFBBFFRLR"]))
+        );
+        assert_eq!("FBFBBFFRLR", tools(&args(&["encode", "44", "5"])));
+        assert_eq!(
+            "Error: row and column must be numbers",
+            tools(&args(&["encode", "44", "left"]))
+        );
+        assert_eq!(
+            "Invalid layout: expected <rows>/<columns>, got '7FB'",
+            tools(&args(&["decode", "FBFBBFFRLR", "7FB"]))
+        );
+        assert!(tools(&args(&["map", "no/such/file"])).starts_with("Error: no/such/file: "));
     }
 }
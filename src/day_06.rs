@@ -1,46 +1,193 @@
-use std::collections::{HashMap, HashSet};
+use crate::tools::read_input;
+use std::str::FromStr;
 
 static INPUT: &'static str = include_str!("assets/day_06_input.txt");
 
-fn get_group_total(input: &str) -> usize {
-    let letters_seen: HashSet<char> = input.chars().filter(char::is_ascii_lowercase).collect();
-    letters_seen.len()
+const QUESTION_COUNT: usize = 26;
+const ALL_QUESTIONS: u32 = (1 << QUESTION_COUNT) - 1;
+
+// One bit per question, with question 'a' in the lowest bit
+type Answers = u32;
+
+fn parse_answers(line: &str) -> Answers {
+    line.chars()
+        .filter(char::is_ascii_lowercase)
+        .fold(0, |answers, c| answers | 1 << (c as u32 - 'a' as u32))
 }
 
-fn get_totals(input: &str) -> Vec<usize> {
-    input.split("\n\n").map(get_group_total).collect()
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quorum {
+    Anyone,
+    Everyone,
+    NoOne,
+    ExactlyOne,
+    AtLeast(usize),
+    // at least numerator/denominator of the group
+    Fraction(usize, usize),
 }
 
-pub fn p1() -> usize {
-    get_totals(INPUT).iter().sum()
+impl FromStr for Quorum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Quorum::Anyone),
+            "all" => Ok(Quorum::Everyone),
+            "none" => Ok(Quorum::NoOne),
+            "one" => Ok(Quorum::ExactlyOne),
+            _ => match s.split_once('/') {
+                Some((numerator, denominator)) => match (numerator.parse(), denominator.parse()) {
+                    (Ok(numerator), Ok(denominator)) if denominator > 0 => {
+                        Ok(Quorum::Fraction(numerator, denominator))
+                    }
+                    _ => Err(format!("invalid fraction '{}'", s)),
+                },
+                None => s
+                    .parse()
+                    .map(Quorum::AtLeast)
+                    .map_err(|_| format!("invalid quorum '{}'", s)),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    people: Vec<Answers>,
 }
 
-fn get_unanimous_group_total(input: &str) -> usize {
-    let group_size = input.lines().count();
-    let mut letters: HashMap<char, usize> = HashMap::new();
+impl Group {
+    fn parse(input: &str) -> Self {
+        Self {
+            people: input.lines().map(parse_answers).collect(),
+        }
+    }
+
+    fn counts(&self) -> [usize; QUESTION_COUNT] {
+        let mut counts = [0; QUESTION_COUNT];
+        for person in &self.people {
+            for (question, count) in counts.iter_mut().enumerate() {
+                if person & (1 << question) != 0 {
+                    *count += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    fn answered_by(&self, quorum: Quorum) -> Answers {
+        let anyone = self.people.iter().fold(0, |acc, person| acc | person);
+        match quorum {
+            Quorum::Anyone => anyone,
+            Quorum::Everyone => self.people.iter().fold(anyone, |acc, person| acc & person),
+            Quorum::NoOne => !anyone & ALL_QUESTIONS,
+            Quorum::ExactlyOne => {
+                let mut twice = 0;
+                let mut once = 0;
+                for person in &self.people {
+                    twice |= once & person;
+                    once |= person;
+                }
+                once & !twice
+            }
+            Quorum::AtLeast(_) | Quorum::Fraction(_, _) => {
+                let threshold = match quorum {
+                    Quorum::AtLeast(k) => k,
+                    // round up, so "half of 3 people" means 2 of them; worked out in u128 so
+                    // huge fractions can't overflow, and anything past usize is out of reach
+                    Quorum::Fraction(numerator, denominator) => {
+                        let threshold = (numerator as u128 * self.people.len() as u128)
+                            .div_ceil(denominator as u128);
+                        threshold.min(usize::MAX as u128) as usize
+                    }
+                    _ => unreachable!(),
+                };
+                self.counts()
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &count)| count >= threshold)
+                    .fold(0, |acc, (question, _)| acc | 1 << question)
+            }
+        }
+    }
+}
+
+fn parse_groups(input: &str) -> Vec<Group> {
     input
-        .chars()
-        .filter(char::is_ascii_lowercase)
-        .for_each(|letter| {
-            letters.insert(letter, letters.get(&letter).unwrap_or(&0) + 1);
-        });
-    letters
+        .split("\n\n")
+        .map(Group::parse)
+        .filter(|group| !group.people.is_empty())
+        .collect()
+}
+
+fn get_quorum_totals(input: &str, quorum: Quorum) -> Vec<usize> {
+    parse_groups(input)
+        .iter()
+        .map(|group| group.answered_by(quorum).count_ones() as usize)
+        .collect()
+}
+
+// How many people answered each question, across all groups
+fn question_histogram(input: &str) -> [usize; QUESTION_COUNT] {
+    parse_groups(input)
         .iter()
-        .filter(|(_, &count)| count == group_size)
-        .count()
+        .fold([0; QUESTION_COUNT], |mut histogram, group| {
+            for (total, count) in histogram.iter_mut().zip(group.counts().iter()) {
+                *total += count;
+            }
+            histogram
+        })
+}
+
+fn get_totals(input: &str) -> Vec<usize> {
+    get_quorum_totals(input, Quorum::Anyone)
+}
+
+pub fn p1() -> usize {
+    get_totals(INPUT).iter().sum()
 }
 
 fn get_unanimous_totals(input: &str) -> Vec<usize> {
-    input.split("\n\n").map(get_unanimous_group_total).collect()
+    get_quorum_totals(input, Quorum::Everyone)
 }
 
 pub fn p2() -> usize {
     get_unanimous_totals(INPUT).iter().sum()
 }
 
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 6 quorum <any|all|none|one|k|a/b> [answers]\n\tadvent2020 6 histogram [answers]";
+    let result = match args {
+        [command, quorum, rest @ ..] if command == "quorum" && rest.len() <= 1 => {
+            quorum.parse::<Quorum>().and_then(|quorum| {
+                let input = read_input(rest.first(), INPUT)?;
+                Ok(get_quorum_totals(&input, quorum)
+                    .iter()
+                    .sum::<usize>()
+                    .to_string())
+            })
+        }
+        [command, rest @ ..] if command == "histogram" && rest.len() <= 1 => {
+            read_input(rest.first(), INPUT).map(|input| {
+                question_histogram(&input)
+                    .iter()
+                    .enumerate()
+                    .map(|(question, count)| {
+                        format!("{}: {}", (b'a' + question as u8) as char, count)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        _ => Ok(usage.to_string()),
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &'static str = r#"abc
 
@@ -87,4 +234,71 @@ b"#;
 
         assert_eq!(sum, 3202);
     }
+
+    #[test]
+    fn quorum_example() {
+        assert_eq!(
+            vec![0, 0, 1, 1, 0],
+            get_quorum_totals(EXAMPLE, Quorum::AtLeast(2))
+        );
+        assert_eq!(
+            vec![3, 3, 2, 0, 1],
+            get_quorum_totals(EXAMPLE, Quorum::ExactlyOne)
+        );
+        assert_eq!(
+            vec![23, 23, 23, 25, 25],
+            get_quorum_totals(EXAMPLE, Quorum::NoOne)
+        );
+        assert_eq!(
+            vec![3, 0, 3, 1, 1],
+            get_quorum_totals(EXAMPLE, Quorum::Fraction(1, 2))
+        );
+        assert_eq!(
+            get_totals(INPUT),
+            get_quorum_totals(INPUT, Quorum::AtLeast(1))
+        );
+        assert_eq!(
+            get_unanimous_totals(INPUT),
+            get_quorum_totals(INPUT, Quorum::Fraction(1, 1))
+        );
+        assert_eq!(
+            vec![0, 0, 0, 0, 0],
+            get_quorum_totals(EXAMPLE, Quorum::Fraction(usize::MAX, 1))
+        );
+        assert_eq!(
+            get_unanimous_totals(EXAMPLE),
+            get_quorum_totals(EXAMPLE, Quorum::Fraction(usize::MAX, usize::MAX))
+        );
+    }
+
+    #[test]
+    fn histogram_example() {
+        let histogram = question_histogram(EXAMPLE);
+
+        assert_eq!([8, 4, 3], histogram[..3]);
+        assert!(histogram[3..].iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn parse_quorum() {
+        assert_eq!(Ok(Quorum::Anyone), "any".parse());
+        assert_eq!(Ok(Quorum::AtLeast(3)), "3".parse());
+        assert_eq!(Ok(Quorum::Fraction(2, 3)), "2/3".parse());
+        assert!("2/0".parse::<Quorum>().is_err());
+        assert!("most".parse::<Quorum>().is_err());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["quorum"])).starts_with("Usage:"));
+        assert_eq!("6633", tools(&args(&["quorum", "any"])));
+        assert_eq!("3202", tools(&args(&["quorum", "1/1"])));
+        assert_eq!(
+            "Error: invalid quorum 'most'",
+            tools(&args(&["quorum", "most"]))
+        );
+        assert!(tools(&args(&["histogram"])).starts_with("a: "));
+        assert!(tools(&args(&["histogram", "no/such/file"])).starts_with("Error: no/such/file: "));
+    }
 }
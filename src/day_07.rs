@@ -1,4 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use crate::tools::read_input;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 static INPUT: &'static str = include_str!("assets/day_07_input.txt");
 
@@ -41,60 +45,255 @@ impl<'a> From<&'a str> for Rule<'a> {
     }
 }
 
+#[derive(Debug, PartialEq)]
+struct Cycle<'a>(Vec<&'a str>);
+
+impl fmt::Display for Cycle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(" -> "))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct BagTree<'a> {
+    bag_color: &'a str,
+    count: usize,
+    children: Vec<BagTree<'a>>,
+}
+
+impl BagTree<'_> {
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(f, "{}{} {}", "  ".repeat(depth), self.count, self.bag_color)?;
+        for child in &self.children {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BagTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DotScope<'a> {
+    All,
+    // the bag and everything it can contain
+    ReachableFrom(&'a str),
+    // the bag and everything that can contain it
+    ReachableTo(&'a str),
+}
+
 #[derive(Debug)]
 struct RuleGraph<'a> {
     rules: HashMap<&'a str, HashMap<&'a str, usize>>,
+    contained_by: HashMap<&'a str, HashSet<&'a str>>,
 }
 
 impl<'a> From<&'a str> for RuleGraph<'a> {
     fn from(s: &'a str) -> Self {
+        let rules: HashMap<&'a str, HashMap<&'a str, usize>> = s
+            .lines()
+            .map(Rule::from)
+            .map(|rule| (rule.bag_color, rule.contains))
+            .collect();
+
+        let mut contained_by: HashMap<&'a str, HashSet<&'a str>> = HashMap::new();
+        for (container, contents) in &rules {
+            for child in contents.keys() {
+                contained_by.entry(child).or_default().insert(container);
+            }
+        }
+
         Self {
-            rules: s
-                .lines()
-                .map(Rule::from)
-                .map(|rule| (rule.bag_color, rule.contains))
-                .collect(),
+            rules,
+            contained_by,
         }
     }
 }
 
 impl<'a> RuleGraph<'a> {
-    fn is_possible_container(&self, bag_color: &'a str, container: &'a str) -> bool {
-        for (child_container, _) in self.rules.get(container).unwrap() {
-            if *child_container == bag_color {
-                return true;
+    // Sorted so that traversals (and any cycle they report) are deterministic
+    fn contents(&self, bag_color: &str) -> Vec<(&'a str, usize)> {
+        let mut contents: Vec<(&'a str, usize)> = self
+            .rules
+            .get(bag_color)
+            .map(|contents| {
+                contents
+                    .iter()
+                    .map(|(&child, &count)| (child, count))
+                    .collect()
+            })
+            .unwrap_or_default();
+        contents.sort_unstable();
+        contents
+    }
+
+    fn containers(&self, bag_color: &'a str) -> HashSet<&'a str> {
+        let mut containers = HashSet::new();
+        let mut queue = vec![bag_color];
+        while let Some(bag) = queue.pop() {
+            for &container in self.contained_by.get(bag).into_iter().flatten() {
+                if containers.insert(container) {
+                    queue.push(container);
+                }
             }
+        }
+        containers
+    }
 
-            let recurse = self.is_possible_container(bag_color, child_container);
-            if recurse {
-                return true;
+    fn contained_bags(&self, bag_color: &'a str) -> HashSet<&'a str> {
+        let mut contained = HashSet::new();
+        let mut queue = vec![bag_color];
+        while let Some(bag) = queue.pop() {
+            for (child, _) in self.contents(bag) {
+                if contained.insert(child) {
+                    queue.push(child);
+                }
             }
         }
-        false
+        contained
     }
 
     fn find_possible_containers(&self, bag_color: &'a str) -> usize {
-        let possible_containers: HashSet<&'a str> = self
-            .rules
-            .keys()
-            .filter(|container| self.is_possible_container(bag_color, container))
-            // dunno how to elegantly avoid this deref mapping
-            .map(|value| *value)
-            .collect();
+        self.containers(bag_color).len()
+    }
+
+    fn visit(
+        &self,
+        bag_color: &'a str,
+        finished: &mut HashSet<&'a str>,
+        path: &mut Vec<&'a str>,
+        order: &mut Vec<&'a str>,
+    ) -> Result<(), Cycle<'a>> {
+        if finished.contains(bag_color) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|&bag| bag == bag_color) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(bag_color);
+            return Err(Cycle(cycle));
+        }
+
+        path.push(bag_color);
+        for (child, _) in self.contents(bag_color) {
+            self.visit(child, finished, path, order)?;
+        }
+        path.pop();
+
+        finished.insert(bag_color);
+        order.push(bag_color);
+        Ok(())
+    }
+
+    // Bags ordered so that every bag comes after everything it contains
+    fn order_from(
+        &self,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<&'a str>, Cycle<'a>> {
+        let mut finished = HashSet::new();
+        let mut order = Vec::new();
+        for root in roots {
+            self.visit(root, &mut finished, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn topological_order(&self) -> Result<Vec<&'a str>, Cycle<'a>> {
+        let mut roots: Vec<&'a str> = self.rules.keys().copied().collect();
+        roots.sort_unstable();
+        self.order_from(roots)
+    }
 
-        possible_containers.len()
+    fn find_cycle(&self) -> Option<Cycle<'a>> {
+        self.topological_order().err()
     }
 
-    fn count_contained_bags(&self, bag_color: &'a str) -> usize {
-        let contained = self.rules.get(bag_color).unwrap();
+    fn count_contained_bags(&self, bag_color: &'a str) -> Result<usize, Cycle<'a>> {
+        let mut totals: HashMap<&'a str, usize> = HashMap::new();
+        for bag in self.order_from(vec![bag_color])? {
+            let total = self
+                .contents(bag)
+                .iter()
+                .map(|(child, count)| count * (1 + totals[child]))
+                .sum();
+            totals.insert(bag, total);
+        }
+        Ok(totals[bag_color])
+    }
+
+    fn nesting_depth(&self, bag_color: &'a str) -> Result<usize, Cycle<'a>> {
+        let mut depths: HashMap<&'a str, usize> = HashMap::new();
+        for bag in self.order_from(vec![bag_color])? {
+            let depth = self
+                .contents(bag)
+                .iter()
+                .map(|(child, _)| 1 + depths[child])
+                .max()
+                .unwrap_or(0);
+            depths.insert(bag, depth);
+        }
+        Ok(depths[bag_color])
+    }
 
-        // inefficient solution
-        let result = contained
-            .iter()
-            .map(|(child, count)| count * (1 + self.count_contained_bags(child)))
-            .sum();
+    fn expand(&self, bag_color: &'a str, count: usize) -> BagTree<'a> {
+        BagTree {
+            bag_color,
+            count,
+            children: self
+                .contents(bag_color)
+                .into_iter()
+                .map(|(child, child_count)| self.expand(child, child_count))
+                .collect(),
+        }
+    }
+
+    fn to_dot(&self, scope: DotScope<'a>) -> String {
+        let (mut nodes, focus) = match scope {
+            DotScope::All => (
+                self.rules
+                    .iter()
+                    .flat_map(|(&container, contents)| {
+                        contents.keys().copied().chain(Some(container))
+                    })
+                    .collect::<HashSet<&'a str>>(),
+                None,
+            ),
+            DotScope::ReachableFrom(bag_color) => (self.contained_bags(bag_color), Some(bag_color)),
+            DotScope::ReachableTo(bag_color) => (self.containers(bag_color), Some(bag_color)),
+        };
+        nodes.extend(focus);
+        let mut sorted_nodes: Vec<&'a str> = nodes.iter().copied().collect();
+        sorted_nodes.sort_unstable();
+
+        let mut dot = String::from("digraph bags {\n");
+        for &node in &sorted_nodes {
+            if Some(node) == focus {
+                dot.push_str(&format!("    \"{}\" [style=filled];\n", node));
+            } else {
+                dot.push_str(&format!("    \"{}\";\n", node));
+            }
+        }
+        for &container in &sorted_nodes {
+            for (child, count) in self.contents(container) {
+                if nodes.contains(child) {
+                    dot.push_str(&format!(
+                        "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                        container, child, count
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
 
-        result
+    fn expansion_tree(&self, bag_color: &'a str) -> Result<BagTree<'a>, Cycle<'a>> {
+        // make sure the expansion is finite before building it
+        self.order_from(vec![bag_color])?;
+        Ok(self.expand(bag_color, 1))
     }
 }
 
@@ -103,12 +302,71 @@ pub fn p1() -> usize {
 }
 
 pub fn p2() -> usize {
-    RuleGraph::from(INPUT).count_contained_bags("shiny gold")
+    RuleGraph::from(INPUT)
+        .count_contained_bags("shiny gold")
+        .expect("bag rules contain a cycle")
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = r#"Usage:
+	advent2020 7 <containers|count|depth|tree> <bag color> [rules]
+	advent2020 7 cycle [rules]
+	advent2020 7 dot [rules]
+	advent2020 7 dot <from|to> <bag color> [rules]"#;
+    let (command, bag_color, path) = match args {
+        [command, rest @ ..] if (command == "cycle" || command == "dot") && rest.len() <= 1 => {
+            (command.to_string(), None, rest.first())
+        }
+        [command, direction, bag_color, rest @ ..] if command == "dot" && rest.len() <= 1 => (
+            format!("dot {}", direction),
+            Some(bag_color.as_str()),
+            rest.first(),
+        ),
+        [command, bag_color, rest @ ..] if rest.len() <= 1 => {
+            (command.to_string(), Some(bag_color.as_str()), rest.first())
+        }
+        _ => return usage.to_string(),
+    };
+    let input = match read_input(path, INPUT) {
+        Ok(input) => input,
+        Err(err) => return err,
+    };
+    let rule_graph = RuleGraph::from(input.as_str());
+
+    let result = match (command.as_str(), bag_color) {
+        ("dot", None) => Ok(rule_graph.to_dot(DotScope::All)),
+        ("dot from", Some(bag_color)) => Ok(rule_graph.to_dot(DotScope::ReachableFrom(bag_color))),
+        ("dot to", Some(bag_color)) => Ok(rule_graph.to_dot(DotScope::ReachableTo(bag_color))),
+        ("cycle", _) => Ok(rule_graph
+            .find_cycle()
+            .map_or("No cycles".to_string(), |cycle| format!("Cycle: {}", cycle))),
+        ("containers", Some(bag_color)) => {
+            let mut containers: Vec<&str> = rule_graph.containers(bag_color).into_iter().collect();
+            containers.sort_unstable();
+            Ok(format!(
+                "{}\n\n{} containers",
+                containers.join("\n"),
+                containers.len()
+            ))
+        }
+        ("count", Some(bag_color)) => rule_graph
+            .count_contained_bags(bag_color)
+            .map(|count| count.to_string()),
+        ("depth", Some(bag_color)) => rule_graph
+            .nesting_depth(bag_color)
+            .map(|depth| depth.to_string()),
+        ("tree", Some(bag_color)) => rule_graph
+            .expansion_tree(bag_color)
+            .map(|tree| tree.to_string()),
+        _ => return usage.to_string(),
+    };
+    result.unwrap_or_else(|cycle| format!("Error: rules contain a cycle: {}", cycle))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
@@ -173,11 +431,155 @@ dark violet bags contain no other bags."#;
         let rule_graph = RuleGraph::from(EXAMPLE);
         let rule_graph2 = RuleGraph::from(EXAMPLE2);
 
-        assert_eq!(32, rule_graph.count_contained_bags("shiny gold"));
-        assert_eq!(126, rule_graph2.count_contained_bags("shiny gold"));
+        assert_eq!(Ok(32), rule_graph.count_contained_bags("shiny gold"));
+        assert_eq!(Ok(126), rule_graph2.count_contained_bags("shiny gold"));
+    }
+
+    #[test]
+    fn p2_correct_answer() {
+        let rule_graph = RuleGraph::from(INPUT);
+
+        assert_eq!(Ok(82372), rule_graph.count_contained_bags("shiny gold"));
     }
 
-    // #[test]
-    // fn p2_correct_answer() {
-    // }
+    #[test]
+    fn reverse_index() {
+        let rule_graph = RuleGraph::from(EXAMPLE);
+
+        assert_eq!(
+            hashset! {"bright white", "muted yellow", "light red", "dark orange"},
+            rule_graph.containers("shiny gold")
+        );
+        assert!(rule_graph.containers("light red").is_empty());
+    }
+
+    #[test]
+    fn topological_order() {
+        let rule_graph = RuleGraph::from(EXAMPLE);
+        let order = rule_graph.topological_order().unwrap();
+        let position = |bag: &str| order.iter().position(|&other| other == bag).unwrap();
+
+        assert_eq!(9, order.len());
+        for (container, contents) in &rule_graph.rules {
+            for child in contents.keys() {
+                assert!(position(child) < position(container));
+            }
+        }
+        assert_eq!(None, rule_graph.find_cycle());
+    }
+
+    #[test]
+    fn nesting_depth() {
+        assert_eq!(Ok(2), RuleGraph::from(EXAMPLE).nesting_depth("shiny gold"));
+        assert_eq!(Ok(6), RuleGraph::from(EXAMPLE2).nesting_depth("shiny gold"));
+        assert_eq!(Ok(0), RuleGraph::from(EXAMPLE).nesting_depth("faded blue"));
+    }
+
+    #[test]
+    fn expansion_tree() {
+        let rule_graph = RuleGraph::from(EXAMPLE);
+        let tree = rule_graph.expansion_tree("dark olive").unwrap();
+
+        assert_eq!(
+            BagTree {
+                bag_color: "dark olive",
+                count: 1,
+                children: vec![
+                    BagTree {
+                        bag_color: "dotted black",
+                        count: 4,
+                        children: vec![],
+                    },
+                    BagTree {
+                        bag_color: "faded blue",
+                        count: 3,
+                        children: vec![],
+                    },
+                ],
+            },
+            tree
+        );
+        assert_eq!(
+            "1 dark olive\n  4 dotted black\n  3 faded blue\n",
+            tree.to_string()
+        );
+    }
+
+    #[test]
+    fn dot_export() {
+        let rule_graph = RuleGraph::from(EXAMPLE);
+
+        assert_eq!(
+            r#"digraph bags {
+    "dark olive";
+    "dotted black";
+    "faded blue";
+    "shiny gold" [style=filled];
+    "vibrant plum";
+    "dark olive" -> "dotted black" [label="4"];
+    "dark olive" -> "faded blue" [label="3"];
+    "shiny gold" -> "dark olive" [label="1"];
+    "shiny gold" -> "vibrant plum" [label="2"];
+    "vibrant plum" -> "dotted black" [label="6"];
+    "vibrant plum" -> "faded blue" [label="5"];
+}
+"#,
+            rule_graph.to_dot(DotScope::ReachableFrom("shiny gold"))
+        );
+        assert_eq!(
+            r#"digraph bags {
+    "bright white";
+    "dark orange";
+    "light red";
+    "muted yellow";
+    "shiny gold" [style=filled];
+    "bright white" -> "shiny gold" [label="1"];
+    "dark orange" -> "bright white" [label="3"];
+    "dark orange" -> "muted yellow" [label="4"];
+    "light red" -> "bright white" [label="1"];
+    "light red" -> "muted yellow" [label="2"];
+    "muted yellow" -> "shiny gold" [label="2"];
+}
+"#,
+            rule_graph.to_dot(DotScope::ReachableTo("shiny gold"))
+        );
+
+        let full = rule_graph.to_dot(DotScope::All);
+        assert_eq!(9, full.lines().filter(|line| line.ends_with("\";")).count());
+        assert_eq!(13, full.matches(" -> ").count());
+    }
+
+    #[test]
+    fn cycle_detection() {
+        let rule_graph = RuleGraph::from(
+            r#"shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag, 3 faded blue bags.
+dark blue bags contain 2 shiny gold bags.
+faded blue bags contain no other bags."#,
+        );
+        let cycle = Cycle(vec!["dark blue", "shiny gold", "dark red", "dark blue"]);
+
+        assert_eq!(Some(&cycle), rule_graph.find_cycle().as_ref());
+        assert_eq!(Err(cycle), rule_graph.nesting_depth("dark blue"));
+        assert!(rule_graph.count_contained_bags("shiny gold").is_err());
+        assert!(rule_graph.expansion_tree("dark red").is_err());
+        assert_eq!(Ok(0), rule_graph.count_contained_bags("faded blue"));
+        assert_eq!(
+            "dark blue -> shiny gold -> dark red -> dark blue",
+            rule_graph.find_cycle().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["count"])).starts_with("Usage:"));
+        assert!(tools(&args(&["dot", "sideways", "shiny gold"])).starts_with("Usage:"));
+        assert_eq!("82372", tools(&args(&["count", "shiny gold"])));
+        assert!(tools(&args(&["containers", "shiny gold"])).ends_with("\n\n169 containers"));
+        assert_eq!("No cycles", tools(&args(&["cycle"])));
+        assert!(
+            tools(&args(&["count", "shiny gold", "no/such/file"])).starts_with("no/such/file: ")
+        );
+    }
 }
@@ -1,134 +1,1198 @@
-use std::collections::HashSet;
+use crate::tools::read_input;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
 
 static INPUT: &'static str = include_str!("assets/day_08_input.txt");
 
-#[derive(Debug, Clone, Copy)]
+const REGISTER_NAMES: [&str; 5] = ["acc", "a", "b", "c", "d"];
+
+// Index into `REGISTER_NAMES`; the accumulator is register 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Register(usize);
+
+impl Register {
+    fn parse(name: &str) -> Option<Self> {
+        REGISTER_NAMES
+            .iter()
+            .position(|&register| register == name)
+            .map(Register)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REGISTER_NAMES[self.0])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Number(i32),
+    Register(Register),
+}
+
+impl Value {
+    fn read(&self, state: &MachineState) -> i32 {
+        match *self {
+            Value::Number(value) => value,
+            Value::Register(register) => state.register(register),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{:+}", value),
+            Value::Register(register) => write!(f, "{}", register),
+        }
+    }
+}
+
+// What running an instruction did, other than changing registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Effect {
+    jump: i32,
+    output: Option<i32>,
+}
+
+impl Effect {
+    fn next() -> Self {
+        Self::jump(1)
+    }
+
+    fn jump(offset: i32) -> Self {
+        Self {
+            jump: offset,
+            output: None,
+        }
+    }
+}
+
+// An instruction outside the original three. `Display` must print it in a form its parser
+// accepts, so programs still round-trip through the assembler.
+trait Operation: fmt::Debug + fmt::Display {
+    fn execute(&self, state: &mut MachineState) -> Effect;
+
+    // Every offset this might jump by; more than one means it depends on register values
+    fn jump_offsets(&self) -> Vec<i32> {
+        vec![1]
+    }
+}
+
+#[derive(Debug, Clone)]
 enum Instruction {
     Acc { value: i32 },
     Jmp { offset: i32 },
     Nop { value: i32 },
+    Extended(Rc<dyn Operation>),
 }
 
-impl From<&str> for Instruction {
-    fn from(s: &str) -> Self {
-        let tokens: Vec<&str> = s.split_whitespace().collect();
-        match tokens.as_slice() {
-            ["acc", value_str] => Instruction::Acc {
-                value: value_str.parse().unwrap(),
-            },
-            ["jmp", offset_str] => Instruction::Jmp {
-                offset: offset_str.parse().unwrap(),
-            },
-            ["nop", value_str] => Instruction::Nop {
-                value: value_str.parse().unwrap(),
-            },
-            _ => panic!("invalid op"),
+// Operations can't be compared directly, so those are equal when they'd be written out the
+// same way
+impl PartialEq for Instruction {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Instruction::Acc { value }, Instruction::Acc { value: other }) => value == other,
+            (Instruction::Jmp { offset }, Instruction::Jmp { offset: other }) => offset == other,
+            (Instruction::Nop { value }, Instruction::Nop { value: other }) => value == other,
+            (Instruction::Extended(operation), Instruction::Extended(other)) => {
+                Rc::ptr_eq(operation, other) || operation.to_string() == other.to_string()
+            }
+            _ => false,
         }
     }
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Acc { value } => write!(f, "acc {:+}", value),
+            Instruction::Jmp { offset } => write!(f, "jmp {:+}", offset),
+            Instruction::Nop { value } => write!(f, "nop {:+}", value),
+            Instruction::Extended(operation) => write!(f, "{}", operation),
+        }
+    }
+}
+
+impl Instruction {
+    fn execute(&self, state: &mut MachineState) -> Effect {
+        match self {
+            Instruction::Acc { value } => {
+                state.acc = state.acc.wrapping_add(*value);
+                Effect::next()
+            }
+            Instruction::Jmp { offset } => Effect::jump(*offset),
+            Instruction::Nop { .. } => Effect::next(),
+            Instruction::Extended(operation) => operation.execute(state),
+        }
+    }
+
+    fn jump_offsets(&self) -> Vec<i32> {
+        match self {
+            Instruction::Jmp { offset } => vec![*offset],
+            Instruction::Extended(operation) => operation.jump_offsets(),
+            _ => vec![1],
+        }
+    }
+
+    fn is_conditional(&self) -> bool {
+        self.jump_offsets().len() > 1
+    }
+
+    // Addresses this might continue at, leaving out jumps outside a program of `len` instructions
+    fn successors(&self, address: usize, len: usize) -> Vec<usize> {
+        self.jump_offsets()
+            .into_iter()
+            .map(|offset| address as i64 + offset as i64)
+            .filter(|&target| target >= 0 && target <= len as i64)
+            .map(|target| target as usize)
+            .collect()
+    }
+
+    // Swap a `jmp` for a `nop` (or vice versa), keeping its argument
+    fn flipped(&self) -> Option<Instruction> {
+        match *self {
+            Instruction::Jmp { offset } => Some(Instruction::Nop { value: offset }),
+            Instruction::Nop { value } => Some(Instruction::Jmp { offset: value }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arithmetic {
+    Set,
+    Add,
+    Mul,
+}
+
+#[derive(Debug)]
+struct RegisterOp {
+    arithmetic: Arithmetic,
+    target: Register,
+    source: Value,
+}
+
+impl fmt::Display for RegisterOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self.arithmetic {
+            Arithmetic::Set => "set",
+            Arithmetic::Add => "add",
+            Arithmetic::Mul => "mul",
+        };
+        write!(f, "{} {} {}", mnemonic, self.target, self.source)
+    }
+}
+
+impl Operation for RegisterOp {
+    fn execute(&self, state: &mut MachineState) -> Effect {
+        let source = self.source.read(state);
+        let target = state.register_mut(self.target);
+        *target = match self.arithmetic {
+            Arithmetic::Set => source,
+            Arithmetic::Add => target.wrapping_add(source),
+            Arithmetic::Mul => target.wrapping_mul(source),
+        };
+        Effect::next()
+    }
+}
+
+#[derive(Debug)]
+struct ConditionalJump {
+    if_zero: bool,
+    register: Register,
+    offset: i32,
+}
+
+impl fmt::Display for ConditionalJump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = if self.if_zero { "jz" } else { "jnz" };
+        write!(f, "{} {} {:+}", mnemonic, self.register, self.offset)
+    }
+}
+
+impl Operation for ConditionalJump {
+    fn execute(&self, state: &mut MachineState) -> Effect {
+        if (state.register(self.register) == 0) == self.if_zero {
+            Effect::jump(self.offset)
+        } else {
+            Effect::next()
+        }
+    }
+
+    fn jump_offsets(&self) -> Vec<i32> {
+        vec![1, self.offset]
+    }
+}
+
 #[derive(Debug)]
+struct Out {
+    source: Value,
+}
+
+impl fmt::Display for Out {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "out {}", self.source)
+    }
+}
+
+impl Operation for Out {
+    fn execute(&self, state: &mut MachineState) -> Effect {
+        Effect {
+            jump: 1,
+            output: Some(self.source.read(state)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Number(i32),
+    // a label, already turned into the offset to it from the instruction using it
+    Label(i32),
+    Register(Register),
+}
+
+impl Operand {
+    fn number(&self) -> Option<i32> {
+        match *self {
+            Operand::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn offset(&self) -> Option<i32> {
+        match *self {
+            Operand::Number(offset) | Operand::Label(offset) => Some(offset),
+            _ => None,
+        }
+    }
+
+    fn register(&self) -> Option<Register> {
+        match *self {
+            Operand::Register(register) => Some(register),
+            _ => None,
+        }
+    }
+
+    fn value(&self) -> Option<Value> {
+        match *self {
+            Operand::Number(value) => Some(Value::Number(value)),
+            Operand::Register(register) => Some(Value::Register(register)),
+            Operand::Label(_) => None,
+        }
+    }
+}
+
+// Builds an instruction from its operands, or `None` if they don't fit
+type OpParser = fn(&[Operand]) -> Option<Instruction>;
+
+fn parse_register_op(arithmetic: Arithmetic, operands: &[Operand]) -> Option<Instruction> {
+    match operands {
+        [target, source] => Some(Instruction::Extended(Rc::new(RegisterOp {
+            arithmetic,
+            target: target.register()?,
+            source: source.value()?,
+        }))),
+        _ => None,
+    }
+}
+
+fn parse_conditional_jump(if_zero: bool, operands: &[Operand]) -> Option<Instruction> {
+    match operands {
+        [register, offset] => Some(Instruction::Extended(Rc::new(ConditionalJump {
+            if_zero,
+            register: register.register()?,
+            offset: offset.offset()?,
+        }))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct InstructionSet {
+    parsers: HashMap<String, OpParser>,
+}
+
+impl InstructionSet {
+    // The original `acc`/`jmp`/`nop` console
+    fn handheld() -> Self {
+        let mut instruction_set = Self {
+            parsers: HashMap::new(),
+        };
+        instruction_set.register("acc", |operands| match operands {
+            [value] => Some(Instruction::Acc {
+                value: value.number()?,
+            }),
+            _ => None,
+        });
+        instruction_set.register("jmp", |operands| match operands {
+            [offset] => Some(Instruction::Jmp {
+                offset: offset.offset()?,
+            }),
+            _ => None,
+        });
+        instruction_set.register("nop", |operands| match operands {
+            [value] => Some(Instruction::Nop {
+                value: value.offset()?,
+            }),
+            _ => None,
+        });
+        instruction_set
+    }
+
+    // The handheld console plus registers `a` to `d`, conditional jumps and output
+    fn extended() -> Self {
+        let mut instruction_set = Self::handheld();
+        instruction_set.register("set", |operands| {
+            parse_register_op(Arithmetic::Set, operands)
+        });
+        instruction_set.register("add", |operands| {
+            parse_register_op(Arithmetic::Add, operands)
+        });
+        instruction_set.register("mul", |operands| {
+            parse_register_op(Arithmetic::Mul, operands)
+        });
+        instruction_set.register("jz", |operands| parse_conditional_jump(true, operands));
+        instruction_set.register("jnz", |operands| parse_conditional_jump(false, operands));
+        instruction_set.register("out", |operands| match operands {
+            [source] => Some(Instruction::Extended(Rc::new(Out {
+                source: source.value()?,
+            }))),
+            _ => None,
+        });
+        instruction_set
+    }
+
+    fn register(&mut self, mnemonic: &str, parser: OpParser) {
+        self.parsers.insert(mnemonic.to_string(), parser);
+    }
+
+    fn parse(&self, mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+        let parser = self
+            .parsers
+            .get(mnemonic)
+            .ok_or_else(|| format!("unknown op '{}'", mnemonic))?;
+        parser(operands).ok_or_else(|| format!("invalid arguments for '{}'", mnemonic))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 struct Program {
-    acc_value: i32,
-    did_terminate: bool,
     instructions: Vec<Instruction>,
 }
 
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
 impl From<&str> for Program {
     fn from(s: &str) -> Self {
+        assemble(s, &InstructionSet::handheld()).expect("invalid program")
+    }
+}
+
+impl Program {
+    fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    fn has_conditional_jumps(&self) -> bool {
+        self.instructions.iter().any(Instruction::is_conditional)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct MachineState {
+    pc: usize,
+    acc: i32,
+    // `a` to `d`, only used by extended instructions
+    registers: [i32; 4],
+    cycles: usize,
+}
+
+impl MachineState {
+    fn register(&self, register: Register) -> i32 {
+        match register.0 {
+            0 => self.acc,
+            index => self.registers[index - 1],
+        }
+    }
+
+    fn register_mut(&mut self, register: Register) -> &mut i32 {
+        match register.0 {
+            0 => &mut self.acc,
+            index => &mut self.registers[index - 1],
+        }
+    }
+
+    // Everything that decides what happens next
+    fn loop_key(&self) -> (usize, i32, [i32; 4]) {
+        (self.pc, self.acc, self.registers)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    // ran off the end of the program, onto the instruction just after the last one
+    Terminated,
+    // about to repeat itself forever; see `Machine::seen_states`
+    Looped { pc: usize },
+    // a jump targeted an address outside the program
+    OutOfBounds { pc: usize, target: i64 },
+    BudgetExhausted,
+}
+
+// Where a machine was, plus how much history it had then. Restoring undoes everything since,
+// so a snapshot only applies to the machine it came from, and only while that history is
+// still there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    state: MachineState,
+    trail_len: usize,
+    output_len: usize,
+    outcome: Option<Outcome>,
+}
+
+#[derive(Debug, Clone)]
+struct Machine {
+    // shared, so cloning a machine only copies its state
+    instructions: Rc<[Instruction]>,
+    state: MachineState,
+    visits: Vec<usize>,
+    // Without conditional jumps, where a program goes never depends on its registers, so
+    // running any instruction twice means it's looping. Otherwise it has to come back to
+    // exactly the same state, which these track.
+    seen_states: Option<HashSet<(usize, i32, [i32; 4])>>,
+    // the state before each instruction run so far, for undoing back to a snapshot
+    trail: Vec<MachineState>,
+    output: Vec<i32>,
+    step_budget: Option<usize>,
+    outcome: Option<Outcome>,
+}
+
+impl Machine {
+    fn new(program: &Program) -> Self {
         Self {
-            acc_value: 0,
-            did_terminate: false,
-            instructions: s.lines().map(Instruction::from).collect(),
+            instructions: program.instructions.clone().into(),
+            state: MachineState::default(),
+            visits: vec![0; program.len()],
+            seen_states: if program.has_conditional_jumps() {
+                Some(HashSet::new())
+            } else {
+                None
+            },
+            trail: Vec::new(),
+            output: Vec::new(),
+            step_budget: None,
+            outcome: None,
+        }
+    }
+
+    fn with_step_budget(mut self, step_budget: usize) -> Self {
+        self.step_budget = Some(step_budget);
+        self
+    }
+
+    fn state(&self) -> MachineState {
+        self.state
+    }
+
+    fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    fn visits(&self, address: usize) -> usize {
+        self.visits[address]
+    }
+
+    fn output(&self) -> &[i32] {
+        &self.output
+    }
+
+    // Patches the running program; other clones of this machine are unaffected
+    fn flip(&mut self, address: usize) -> bool {
+        let flipped = match self
+            .instructions
+            .get(address)
+            .and_then(Instruction::flipped)
+        {
+            Some(flipped) => flipped,
+            None => return false,
+        };
+        let mut instructions = self.instructions.to_vec();
+        instructions[address] = flipped;
+        self.instructions = instructions.into();
+        true
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.state,
+            trail_len: self.trail.len(),
+            output_len: self.output.len(),
+            outcome: self.outcome,
+        }
+    }
+
+    // Winds back through the trail; each state in it was only ever seen once, or the machine
+    // would have stopped there
+    fn restore(&mut self, snapshot: &Snapshot) {
+        while self.trail.len() > snapshot.trail_len {
+            let state = self.trail.pop().unwrap();
+            self.visits[state.pc] -= 1;
+            if let Some(seen_states) = &mut self.seen_states {
+                seen_states.remove(&state.loop_key());
+            }
+        }
+        self.output.truncate(snapshot.output_len);
+        self.state = snapshot.state;
+        self.outcome = snapshot.outcome;
+    }
+
+    fn check_halted(&self) -> Option<Outcome> {
+        let pc = self.state.pc;
+        let looped = match &self.seen_states {
+            Some(seen_states) => seen_states.contains(&self.state.loop_key()),
+            None => self.visits.get(pc).is_some_and(|&visits| visits > 0),
+        };
+        if self.outcome.is_some() {
+            self.outcome
+        } else if pc == self.instructions.len() {
+            Some(Outcome::Terminated)
+        } else if looped {
+            Some(Outcome::Looped { pc })
+        } else if self
+            .step_budget
+            .is_some_and(|budget| self.state.cycles >= budget)
+        {
+            Some(Outcome::BudgetExhausted)
+        } else {
+            None
+        }
+    }
+
+    // Runs a single instruction, or returns why the machine can't
+    fn step(&mut self) -> Option<Outcome> {
+        if let Some(outcome) = self.check_halted() {
+            self.outcome = Some(outcome);
+            return self.outcome;
+        }
+
+        let pc = self.state.pc;
+        let instructions = Rc::clone(&self.instructions);
+        let mut state = self.state;
+        let effect = instructions[pc].execute(&mut state);
+        let target = pc as i64 + effect.jump as i64;
+        if target < 0 || target > self.instructions.len() as i64 {
+            self.outcome = Some(Outcome::OutOfBounds { pc, target });
+            return self.outcome;
+        }
+
+        if let Some(seen_states) = &mut self.seen_states {
+            seen_states.insert(self.state.loop_key());
+        }
+        self.visits[pc] += 1;
+        self.trail.push(self.state);
+        self.output.extend(effect.output);
+        self.state = MachineState {
+            pc: target as usize,
+            cycles: self.state.cycles + 1,
+            ..state
+        };
+        None
+    }
+
+    fn run(&mut self) -> Outcome {
+        loop {
+            if let Some(outcome) = self.step() {
+                return outcome;
+            }
         }
     }
 }
 
-impl Program {
-    fn reset(&mut self) {
-        self.acc_value = 0;
-        self.did_terminate = false;
-    }
-
-    fn run_until_loop(&mut self) {
-        self.reset();
-        let mut instructions_hit: HashSet<usize> = HashSet::new();
-        let mut ptr: usize = 0;
-        while ptr < self.instructions.len() {
-            if instructions_hit.contains(&ptr) {
-                return;
+// Programs with conditional jumps might never halt, so analysing them gives up eventually
+const ANALYSIS_STEP_BUDGET: usize = 1_000_000;
+
+fn analysis_machine(program: &Program) -> Machine {
+    let machine = Machine::new(program);
+    if program.has_conditional_jumps() {
+        machine.with_step_budget(ANALYSIS_STEP_BUDGET)
+    } else {
+        machine
+    }
+}
+
+fn run_until_loop(program: &Program) -> i32 {
+    let mut machine = Machine::new(program);
+    match machine.run() {
+        Outcome::Looped { .. } => machine.state().acc,
+        outcome => panic!("expected a loop, got {:?}", outcome),
+    }
+}
+
+// Whether the unmodified program can run off the end when started from each address (with
+// one extra entry for the end itself), found by walking the control flow graph backwards
+fn terminating_addresses(program: &Program) -> Vec<bool> {
+    let len = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); len + 1];
+    for (address, instruction) in program.instructions.iter().enumerate() {
+        for next in instruction.successors(address, len) {
+            predecessors[next].push(address);
+        }
+    }
+
+    let mut terminates = vec![false; len + 1];
+    terminates[len] = true;
+    let mut queue = vec![len];
+    while let Some(address) = queue.pop() {
+        for &predecessor in &predecessors[address] {
+            if !terminates[predecessor] {
+                terminates[predecessor] = true;
+                queue.push(predecessor);
             }
-            instructions_hit.insert(ptr);
-            match self.instructions[ptr] {
-                Instruction::Acc { value } => {
-                    self.acc_value += value;
+        }
+    }
+    terminates
+}
+
+// Runs the machine on from a snapshot with the instruction there flipped, then puts it back:
+// restored to the snapshot, with the instruction flipped back. Returns how the patched program
+// halted and its final state.
+fn run_patched(machine: &mut Machine, snapshot: &Snapshot) -> (Outcome, MachineState) {
+    machine.restore(snapshot);
+    machine.flip(snapshot.state.pc);
+    let outcome = machine.run();
+    let state = machine.state();
+    machine.restore(snapshot);
+    machine.flip(snapshot.state.pc);
+    (outcome, state)
+}
+
+// Snapshots of the original run on reaching every `jmp`/`nop` whose flip makes the program
+// terminate. Only instructions on the original path can change anything, and a flipped
+// instruction terminates exactly when its new target already reached the end without passing
+// back through it. Conditional jumps make that graph an over-estimate, so those candidates
+// still need running to be sure. Only the first visit to an address counts, since a flip there
+// changes the path before any later visit. `machine` should be fresh, and is left at the end
+// of the original run for branching off the snapshots.
+fn repair_candidates(program: &Program, machine: &mut Machine) -> Vec<Snapshot> {
+    let terminates = terminating_addresses(program);
+    let mut candidates = Vec::new();
+    while machine.check_halted().is_none() {
+        let pc = machine.state().pc;
+        let flipped = program.instructions.get(pc).and_then(Instruction::flipped);
+        if machine.visits(pc) == 0
+            && flipped.is_some_and(|flipped| {
+                flipped
+                    .successors(pc, program.len())
+                    .iter()
+                    .any(|&target| terminates[target])
+            })
+        {
+            candidates.push(machine.snapshot());
+        }
+        machine.step();
+    }
+    candidates
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum RepairError {
+    // the program runs off the end as it is, so there's nothing to repair
+    AlreadyTerminates,
+    // every address whose flip repairs the program, when there isn't exactly one
+    Candidates(Vec<usize>),
+}
+
+// Returns the flipped address and the final accumulator of the repaired program
+fn fix_corruption_and_run(program: &Program) -> Result<(usize, i32), RepairError> {
+    let mut machine = analysis_machine(program);
+    let candidates = repair_candidates(program, &mut machine);
+    // the control flow analysis assumes the original run doesn't reach the end
+    if machine.check_halted() == Some(Outcome::Terminated) {
+        return Err(RepairError::AlreadyTerminates);
+    }
+    if !program.has_conditional_jumps() && candidates.len() != 1 {
+        return Err(RepairError::Candidates(
+            candidates
+                .iter()
+                .map(|snapshot| snapshot.state.pc)
+                .collect(),
+        ));
+    }
+
+    // latest first, so branching off each one only undoes history the rest don't need
+    let mut repairs = Vec::new();
+    for snapshot in candidates.iter().rev() {
+        if let (Outcome::Terminated, state) = run_patched(&mut machine, snapshot) {
+            repairs.push((snapshot.state.pc, state.acc));
+        }
+    }
+    repairs.reverse();
+    match repairs.as_slice() {
+        [repair] => Ok(*repair),
+        _ => Err(RepairError::Candidates(
+            repairs.iter().map(|&(address, _)| address).collect(),
+        )),
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_operand(
+    token: &str,
+    address: usize,
+    labels: &HashMap<&str, usize>,
+) -> Result<Operand, String> {
+    if let Ok(value) = token.parse() {
+        Ok(Operand::Number(value))
+    } else if let Some(register) = Register::parse(token) {
+        Ok(Operand::Register(register))
+    } else if let Some(&target) = labels.get(token) {
+        Ok(Operand::Label(target as i32 - address as i32))
+    } else {
+        Err(format!("unknown label, register or number '{}'", token))
+    }
+}
+
+// Assembles source where each line holds an optional `label:`, an optional instruction and an
+// optional `#` comment. Arguments are numbers, register names or labels, which are turned
+// into the offset from the instruction to the label. Plain puzzle input is valid source as-is.
+fn assemble(source: &str, instruction_set: &InstructionSet) -> Result<Program, String> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut lines: Vec<(usize, Vec<&str>)> = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut code = line.split('#').next().unwrap().trim();
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) || Register::parse(label).is_some() {
+                return Err(format!("line {}: invalid label '{}'", line_number, label));
+            }
+            if labels.insert(label, lines.len()).is_some() {
+                return Err(format!("line {}: duplicate label '{}'", line_number, label));
+            }
+            code = rest.trim();
+        }
+        if !code.is_empty() {
+            lines.push((line_number, code.split_whitespace().collect()));
+        }
+    }
+
+    let instructions = lines
+        .iter()
+        .enumerate()
+        .map(|(address, (line_number, tokens))| {
+            let operands = tokens[1..]
+                .iter()
+                .map(|token| parse_operand(token, address, &labels))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|operands| instruction_set.parse(tokens[0], &operands));
+            operands.map_err(|err| format!("line {}: {}", line_number, err))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Program { instructions })
+}
+
+// The addresses the program actually runs, and the ones it ends up looping over
+fn trace_path(program: &Program) -> (HashSet<usize>, HashSet<usize>) {
+    let mut machine = analysis_machine(program);
+    let mut states = Vec::new();
+    while machine.check_halted().is_none() {
+        states.push(machine.state());
+        machine.step();
+    }
+    let reachable = states.iter().map(|state| state.pc).collect();
+
+    let current = machine.state();
+    let loop_start = match machine.check_halted() {
+        Some(Outcome::Looped { .. }) if program.has_conditional_jumps() => states
+            .iter()
+            .position(|state| state.loop_key() == current.loop_key()),
+        Some(Outcome::Looped { pc }) => states.iter().position(|state| state.pc == pc),
+        _ => None,
+    };
+    let in_loop = loop_start.map_or(HashSet::new(), |start| {
+        states[start..].iter().map(|state| state.pc).collect()
+    });
+    (reachable, in_loop)
+}
+
+fn jump_target(instruction: &Instruction, address: usize, len: usize) -> Option<usize> {
+    match instruction {
+        Instruction::Jmp { .. } => instruction.successors(address, len).first().copied(),
+        _ => None,
+    }
+}
+
+// Writes the program back out as source, labelling jump targets and annotating each
+// instruction with its address, whether it runs, and whether it's part of the final loop.
+// Annotations are comments, so the output assembles back into the same program.
+fn disassemble(program: &Program) -> String {
+    let (reachable, in_loop) = trace_path(program);
+    let targets: BTreeSet<usize> = program
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(address, instruction)| jump_target(instruction, address, program.len()))
+        .collect();
+
+    let mut source = String::new();
+    for (address, instruction) in program.instructions.iter().enumerate() {
+        if targets.contains(&address) {
+            source.push_str(&format!("L{}:\n", address));
+        }
+        let code = match jump_target(instruction, address, program.len()) {
+            Some(target) => format!("jmp L{}", target),
+            None => instruction.to_string(),
+        };
+        let mut notes = vec![if reachable.contains(&address) {
+            "reachable"
+        } else {
+            "unreachable"
+        }];
+        if in_loop.contains(&address) {
+            notes.push("loop");
+        }
+        source.push_str(&format!(
+            "    {:<12}# {:>4}: {}\n",
+            code,
+            address,
+            notes.join(", ")
+        ));
+    }
+    if targets.contains(&program.len()) {
+        source.push_str(&format!("L{}:\n", program.len()));
+    }
+    source
+}
+
+#[derive(Debug)]
+struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<usize>,
+    watch_acc: bool,
+    // address of every instruction run so far, in order
+    trace: Vec<usize>,
+    // how far `continue` runs before pausing, for programs that might never halt
+    continue_budget: Option<usize>,
+}
+
+static DEBUGGER_HELP: &str = r#"Commands:
+    break <addr>      add a breakpoint
+    delete <addr>     remove a breakpoint
+    step [n]          run n instructions (default 1)
+    continue          run until a breakpoint or the program halts
+    watch             toggle printing accumulator changes
+    toggle <addr>     swap jmp/nop at an address
+    list              show the program with visit counts
+    trace             show every instruction run so far
+    state             show pc, registers and cycle count
+    quit              leave the debugger"#;
+
+impl Debugger {
+    fn new(program: &Program) -> Self {
+        Self {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watch_acc: false,
+            trace: Vec::new(),
+            continue_budget: if program.has_conditional_jumps() {
+                Some(ANALYSIS_STEP_BUDGET)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn describe_state(&self) -> String {
+        let state = self.machine.state();
+        let instruction = self
+            .machine
+            .instructions()
+            .get(state.pc)
+            .map_or("<end of program>".to_string(), Instruction::to_string);
+        let registers: String = state
+            .registers
+            .iter()
+            .enumerate()
+            .filter(|&(_, &value)| value != 0)
+            .map(|(index, value)| format!("{}: {}  ", Register(index + 1), value))
+            .collect();
+        format!(
+            "pc: {}  acc: {}  {}cycles: {}  next: {}",
+            state.pc, state.acc, registers, state.cycles, instruction
+        )
+    }
+
+    fn step(&mut self, output: &mut Vec<String>) -> Option<Outcome> {
+        let before = self.machine.state();
+        let output_before = self.machine.output().len();
+        let outcome = self.machine.step();
+        match outcome {
+            Some(outcome) => output.push(format!("Halted: {:?}", outcome)),
+            None => {
+                self.trace.push(before.pc);
+                for value in &self.machine.output()[output_before..] {
+                    output.push(format!("out: {}", value));
                 }
-                Instruction::Jmp { offset } => {
-                    ptr = (ptr as i32 + offset) as usize;
-                    continue;
+                let acc = self.machine.state().acc;
+                if self.watch_acc && acc != before.acc {
+                    output.push(format!("acc: {} -> {} (at {})", before.acc, acc, before.pc));
                 }
-                Instruction::Nop { .. } => {}
             }
-            ptr += 1;
         }
-
-        self.did_terminate = true;
+        outcome
     }
 
-    fn read_acc(&self) -> i32 {
-        self.acc_value
+    fn parse_address(&self, arg: Option<&&str>) -> Result<usize, String> {
+        let address: usize = arg
+            .ok_or("missing address")?
+            .parse()
+            .map_err(|_| "invalid address")?;
+        if address >= self.machine.instructions().len() {
+            return Err(format!("address {} is outside the program", address));
+        }
+        Ok(address)
     }
 
-    fn fix_corruption_and_run(&mut self) {
-        let mut modify_ptr: usize = 0;
-        let instruction_count = self.instructions.len();
-        while modify_ptr < instruction_count {
-            let op: Instruction = self.instructions[modify_ptr];
-            match op {
-                Instruction::Nop { value } => {
-                    self.instructions[modify_ptr] = Instruction::Jmp { offset: value };
-                    self.run_until_loop();
-                    if self.did_terminate {
-                        println!("Modified instruction at {}", modify_ptr);
-                        return;
+    // Returns `None` once the user asks to quit
+    fn command(&mut self, line: &str) -> Option<String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let mut output = Vec::new();
+        let result: Result<(), String> = match tokens.first().copied() {
+            None => Ok(()),
+            Some("quit") | Some("q") => return None,
+            Some("help") => {
+                output.push(DEBUGGER_HELP.to_string());
+                Ok(())
+            }
+            Some("break") | Some("b") => self.parse_address(tokens.get(1)).map(|address| {
+                self.breakpoints.insert(address);
+                output.push(format!("Breakpoint at {}", address));
+            }),
+            Some("delete") => self.parse_address(tokens.get(1)).map(|address| {
+                if self.breakpoints.remove(&address) {
+                    output.push(format!("Removed breakpoint at {}", address));
+                } else {
+                    output.push(format!("No breakpoint at {}", address));
+                }
+            }),
+            Some("step") | Some("s") => match tokens.get(1).map_or(Ok(1), |n| n.parse()) {
+                Ok(count) => {
+                    for _ in 0..count {
+                        if self.step(&mut output).is_some() {
+                            break;
+                        }
                     }
-                    // restore
-                    self.instructions[modify_ptr] = Instruction::Nop { value: value };
+                    output.push(self.describe_state());
+                    Ok(())
                 }
-                Instruction::Jmp { offset } => {
-                    self.instructions[modify_ptr] = Instruction::Nop { value: offset };
-                    self.run_until_loop();
-                    if self.did_terminate {
-                        println!("Modified instruction at {}", modify_ptr);
-                        return;
+                Err(_) => Err("invalid step count".to_string()),
+            },
+            Some("continue") | Some("c") => {
+                let mut steps = 0;
+                while self.step(&mut output).is_none() {
+                    steps += 1;
+                    let pc = self.machine.state().pc;
+                    if self.breakpoints.contains(&pc) {
+                        output.push(format!("Hit breakpoint at {}", pc));
+                        break;
+                    }
+                    if self.continue_budget == Some(steps) {
+                        output.push(format!("Paused after {} steps", steps));
+                        break;
                     }
-                    // restore
-                    self.instructions[modify_ptr] = Instruction::Jmp { offset: offset };
                 }
-                Instruction::Acc { .. } => {}
+                output.push(self.describe_state());
+                Ok(())
+            }
+            Some("watch") => {
+                self.watch_acc = !self.watch_acc;
+                output.push(format!(
+                    "Watching accumulator: {}",
+                    if self.watch_acc { "on" } else { "off" }
+                ));
+                Ok(())
+            }
+            Some("toggle") => self.parse_address(tokens.get(1)).and_then(|address| {
+                if self.machine.flip(address) {
+                    output.push(format!(
+                        "{}: {}",
+                        address,
+                        self.machine.instructions()[address]
+                    ));
+                    Ok(())
+                } else {
+                    Err(format!("instruction at {} is not a jmp or nop", address))
+                }
+            }),
+            Some("list") => {
+                let pc = self.machine.state().pc;
+                for (address, instruction) in self.machine.instructions().iter().enumerate() {
+                    output.push(format!(
+                        "{}{} {:>5}  {}  visits: {}",
+                        if address == pc { '>' } else { ' ' },
+                        if self.breakpoints.contains(&address) {
+                            '*'
+                        } else {
+                            ' '
+                        },
+                        address,
+                        instruction,
+                        self.machine.visits(address)
+                    ));
+                }
+                Ok(())
+            }
+            Some("trace") => {
+                for (cycle, &address) in self.trace.iter().enumerate() {
+                    output.push(format!(
+                        "{:>5}  {:>5}  {}  visits: {}",
+                        cycle,
+                        address,
+                        self.machine.instructions()[address],
+                        self.machine.visits(address)
+                    ));
+                }
+                Ok(())
+            }
+            Some("state") => {
+                output.push(self.describe_state());
+                Ok(())
             }
-            modify_ptr += 1;
+            Some(command) => Err(format!("unknown command '{}', try 'help'", command)),
+        };
+
+        if let Err(err) = result {
+            output.push(format!("Error: {}", err));
         }
+        Some(output.join("\n"))
     }
 }
 
-pub fn p1() -> i32 {
-    let mut program = Program::from(INPUT);
-    program.run_until_loop();
+fn run_debugger(program: &Program, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut debugger = Debugger::new(program);
+    writeln!(output, "{}", debugger.describe_state())?;
+    write!(output, "(debug) ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match debugger.command(&line?) {
+            Some(response) if response.is_empty() => {}
+            Some(response) => writeln!(output, "{}", response)?,
+            None => break,
+        }
+        write!(output, "(debug) ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
 
-    program.read_acc()
+pub fn p1() -> i32 {
+    run_until_loop(&Program::from(INPUT))
 }
 
 pub fn p2() -> i32 {
-    let mut program = Program::from(INPUT);
-    program.fix_corruption_and_run();
+    let (_, acc) = fix_corruption_and_run(&Program::from(INPUT)).expect("no unique repair");
+    acc
+}
 
-    program.read_acc()
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 8 run [program] [step budget]\n\tadvent2020 8 <debug|repair|asm|disasm> [program]";
+    let (command, path, budget) = match args {
+        [command, rest @ ..] if command == "run" && rest.len() <= 2 => {
+            (command, rest.first(), rest.get(1))
+        }
+        [command, rest @ ..]
+            if ["debug", "repair", "asm", "disasm"].contains(&command.as_str())
+                && rest.len() <= 1 =>
+        {
+            (command, rest.first(), None)
+        }
+        _ => return usage.to_string(),
+    };
+    let input = match read_input(path, INPUT) {
+        Ok(input) => input,
+        Err(err) => return err,
+    };
+
+    let program = match assemble(&input, &InstructionSet::extended()) {
+        Ok(program) => program,
+        Err(err) => return format!("Invalid program: {}", err),
+    };
+    if command == "asm" {
+        return program.to_string();
+    }
+    if command == "disasm" {
+        return disassemble(&program);
+    }
+    if command == "repair" {
+        return match fix_corruption_and_run(&program) {
+            Ok((address, acc)) => format!("Patched address {}\nacc: {}", address, acc),
+            Err(RepairError::AlreadyTerminates) => "The program already terminates".to_string(),
+            Err(RepairError::Candidates(candidates)) if candidates.is_empty() => {
+                "No single-flip repair exists".to_string()
+            }
+            Err(RepairError::Candidates(candidates)) => format!(
+                "Multiple single-flip repairs exist: {}",
+                candidates
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+    }
+    if command == "debug" {
+        return match run_debugger(&program, io::stdin().lock(), io::stdout()) {
+            Ok(()) => "Debugger exited".to_string(),
+            Err(err) => format!("Debugger failed: {}", err),
+        };
+    }
+
+    let mut machine = analysis_machine(&program);
+    if let Some(budget) = budget {
+        match budget.parse() {
+            Ok(budget) => machine = machine.with_step_budget(budget),
+            Err(_) => return usage.to_string(),
+        }
+    }
+    let outcome = machine.run();
+    let state = machine.state();
+    let mut report = format!(
+        "Outcome: {:?}\npc: {}\nacc: {}\ncycles: {}",
+        outcome, state.pc, state.acc, state.cycles
+    );
+    if !machine.output().is_empty() {
+        let output: Vec<String> = machine.output().iter().map(i32::to_string).collect();
+        report.push_str(&format!("\noutput: {}", output.join(" ")));
+    }
+    report
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"nop +0
 acc +1
@@ -142,33 +1206,383 @@ acc +6"#;
 
     #[test]
     fn p1_example() {
-        let mut program = Program::from(EXAMPLE);
-        program.run_until_loop();
-
-        assert_eq!(5, program.read_acc())
+        assert_eq!(5, run_until_loop(&Program::from(EXAMPLE)))
     }
 
     #[test]
     fn p1_correct_answer() {
-        let mut program = Program::from(INPUT);
-        program.run_until_loop();
-
-        assert_eq!(1675, program.read_acc())
+        assert_eq!(1675, run_until_loop(&Program::from(INPUT)))
     }
 
     #[test]
     fn p2_example() {
-        let mut program = Program::from(EXAMPLE);
-        program.fix_corruption_and_run();
-
-        assert_eq!(8, program.read_acc())
+        assert_eq!(Ok((7, 8)), fix_corruption_and_run(&Program::from(EXAMPLE)))
     }
 
     #[test]
     fn p2_correct_answer() {
-        let mut program = Program::from(INPUT);
-        program.fix_corruption_and_run();
+        let (_, acc) = fix_corruption_and_run(&Program::from(INPUT)).unwrap();
+
+        assert_eq!(1532, acc)
+    }
+
+    #[test]
+    fn terminating_addresses_example() {
+        let terminates = terminating_addresses(&Program::from(EXAMPLE));
+
+        assert_eq!(
+            vec![false, false, false, false, false, false, false, false, true, true],
+            terminates
+        );
+    }
+
+    #[test]
+    fn ambiguous_and_impossible_repairs() {
+        let ambiguous = Program::from("nop +2\njmp +0\nacc +1");
+        assert_eq!(
+            Err(RepairError::Candidates(vec![0, 1])),
+            fix_corruption_and_run(&ambiguous)
+        );
+
+        let impossible = Program::from("jmp +0\nacc +1\njmp -1");
+        assert_eq!(
+            Err(RepairError::Candidates(vec![])),
+            fix_corruption_and_run(&impossible)
+        );
+
+        // jumping out of bounds isn't termination
+        let out_of_bounds = Program::from("jmp +0\njmp +2");
+        assert_eq!(
+            Err(RepairError::Candidates(vec![])),
+            fix_corruption_and_run(&out_of_bounds)
+        );
+
+        // nothing to repair, and flipping the nop would only make it loop
+        let terminates = Program::from("nop +0\nacc +1");
+        assert_eq!(
+            Err(RepairError::AlreadyTerminates),
+            fix_corruption_and_run(&terminates)
+        );
+    }
+
+    #[test]
+    fn machine_outcomes() {
+        let mut machine = Machine::new(&Program::from(EXAMPLE));
+        assert_eq!(Outcome::Looped { pc: 1 }, machine.run());
+        assert_eq!(
+            MachineState {
+                pc: 1,
+                acc: 5,
+                registers: [0; 4],
+                cycles: 7
+            },
+            machine.state()
+        );
+        // halting is sticky
+        assert_eq!(Some(Outcome::Looped { pc: 1 }), machine.step());
+
+        let mut machine = Machine::new(&Program::from("nop +0\nacc +2\njmp -3"));
+        assert_eq!(Outcome::OutOfBounds { pc: 2, target: -1 }, machine.run());
+        assert_eq!(2, machine.state().acc);
+
+        let mut machine = Machine::new(&Program::from("jmp +2\nacc +1"));
+        assert_eq!(Outcome::Terminated, machine.run());
+
+        let mut machine = Machine::new(&Program::from("jmp +3\nacc +1"));
+        assert_eq!(Outcome::OutOfBounds { pc: 0, target: 3 }, machine.run());
+    }
+
+    #[test]
+    fn accumulator_wraps() {
+        let mut machine = Machine::new(&Program::from("acc +2000000000\nacc +2000000000"));
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(-294_967_296, machine.state().acc);
+
+        // the same as the extended `add`
+        let program = assemble(
+            "add acc +2000000000\nadd acc +2000000000",
+            &InstructionSet::extended(),
+        );
+        let mut machine = Machine::new(&program.unwrap());
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(-294_967_296, machine.state().acc);
+    }
+
+    #[test]
+    fn step_budget() {
+        let mut machine = Machine::new(&Program::from(EXAMPLE)).with_step_budget(3);
+        assert_eq!(Outcome::BudgetExhausted, machine.run());
+        assert_eq!(
+            MachineState {
+                pc: 6,
+                acc: 1,
+                registers: [0; 4],
+                cycles: 3
+            },
+            machine.state()
+        );
+
+        // a budget the machine has already gone past still stops it
+        let mut machine = Machine::new(&Program::from(EXAMPLE));
+        for _ in 0..3 {
+            machine.step();
+        }
+        let mut limited = machine.with_step_budget(2);
+        assert_eq!(Outcome::BudgetExhausted, limited.run());
+        assert_eq!(3, limited.state().cycles);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut machine = Machine::new(&Program::from(EXAMPLE));
+        assert_eq!(None, machine.step());
+        assert_eq!(None, machine.step());
+        let snapshot = machine.snapshot();
+
+        let mut fork = machine.clone();
+        assert_eq!(Outcome::Looped { pc: 1 }, fork.run());
+        assert_eq!(2, machine.state().cycles);
+
+        assert_eq!(Outcome::Looped { pc: 1 }, machine.run());
+        machine.restore(&snapshot);
+        assert_eq!(snapshot, machine.snapshot());
+        assert_eq!((1, 0), (machine.visits(1), machine.visits(2)));
+        assert_eq!(Outcome::Looped { pc: 1 }, machine.run());
+        assert_eq!(5, machine.state().acc);
+
+        // restoring undoes registers, output and loop tracking too
+        let program = assemble(
+            "set a 2\nout a\nadd a -1\njnz a -2",
+            &InstructionSet::extended(),
+        );
+        let mut machine = Machine::new(&program.unwrap());
+        machine.step();
+        let snapshot = machine.snapshot();
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(vec![2, 1], machine.output());
+        machine.restore(&snapshot);
+        assert!(machine.output().is_empty());
+        assert_eq!(2, machine.state().register(Register(1)));
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(vec![2, 1], machine.output());
+    }
+
+    #[test]
+    fn debugger_session() {
+        let script = "break 7\nwatch\ncontinue\ntoggle 7\nstep 3\nlist\nquit\nstate\n";
+        let mut output = Vec::new();
+        run_debugger(&Program::from(EXAMPLE), script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("acc: 0 -> 1 (at 1)"));
+        assert!(output.contains("Hit breakpoint at 7"));
+        assert!(output.contains("pc: 7  acc: 2  cycles: 4  next: jmp -4"));
+        assert!(output.contains("7: nop -4"));
+        assert!(output.contains("acc: 2 -> 8 (at 8)"));
+        assert!(output.contains("Halted: Terminated"));
+        assert!(output.contains("pc: 9  acc: 8  cycles: 6  next: <end of program>"));
+        assert!(output.contains(" *     7  nop -4  visits: 1"));
+        // nothing runs after `quit`
+        assert_eq!(1, output.matches("Halted").count());
+    }
+
+    #[test]
+    fn debugger_trace_and_errors() {
+        let mut debugger = Debugger::new(&Program::from(EXAMPLE));
+
+        assert_eq!(
+            Some("Error: address 9 is outside the program".to_string()),
+            debugger.command("break 9")
+        );
+        assert_eq!(
+            Some("Error: instruction at 1 is not a jmp or nop".to_string()),
+            debugger.command("toggle 1")
+        );
+        debugger.command("continue");
+        let trace = debugger.command("trace").unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(7, lines.len());
+        assert_eq!("    0      0  nop +0  visits: 1", lines[0]);
+        assert_eq!("    6      4  jmp -3  visits: 1", lines[6]);
+        assert_eq!(None, debugger.command("quit"));
+    }
+
+    #[test]
+    fn debugger_continue_budget() {
+        let program = assemble("add a +1\njnz a -1", &InstructionSet::extended()).unwrap();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(Some(ANALYSIS_STEP_BUDGET), debugger.continue_budget);
+
+        debugger.continue_budget = Some(10);
+        let output = debugger.command("continue").unwrap();
+        assert!(output.contains("Paused after 10 steps"));
+        assert!(output.contains("a: 5  cycles: 10"));
+        assert!(debugger.command("continue").unwrap().contains("cycles: 20"));
+
+        assert_eq!(None, Debugger::new(&Program::from(EXAMPLE)).continue_budget);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let program = Program::from(INPUT);
+
+        assert_eq!(format!("{}\n", INPUT.trim_end()), program.to_string());
+        assert_eq!(
+            Ok(program.clone()),
+            assemble(&program.to_string(), &InstructionSet::handheld())
+        );
+        assert_eq!(
+            Ok(program.clone()),
+            assemble(&disassemble(&program), &InstructionSet::handheld())
+        );
+    }
+
+    #[test]
+    fn assemble_labels_and_comments() {
+        let source = r#"
+# count to three, then stop
+start:  nop +0
+loop:
+    acc +1      # bump
+    jmp check
+    jmp loop
+check: acc +0
+    jmp end
+end:
+"#;
+        let handheld = InstructionSet::handheld();
+        let program = assemble(source, &handheld).unwrap();
+
+        assert_eq!(
+            "nop +0\nacc +1\njmp +2\njmp -2\nacc +0\njmp +1\n",
+            program.to_string()
+        );
+        assert!(assemble("jmp nowhere", &handheld).is_err());
+        assert!(assemble("x:\nx: nop +0", &handheld).is_err());
+        assert!(assemble("a: nop +0", &handheld).is_err());
+        assert!(assemble("x: acc x", &handheld).is_err());
+        assert!(assemble("mul +2", &handheld).is_err());
+        assert!(assemble("acc", &handheld).is_err());
+        assert_eq!(
+            Err("line 2: unknown label, register or number 'nowhere'".to_string()),
+            assemble("nop +0\njmp nowhere", &handheld)
+        );
+        assert_eq!(
+            Err("line 1: unknown op 'out'".to_string()),
+            assemble("out +1", &handheld)
+        );
+    }
+
+    #[test]
+    fn extended_instructions() {
+        let source = r#"
+    set a 3
+loop:
+    out a
+    add a -1
+    jnz a loop
+    mul acc 2
+    acc +7
+"#;
+        let extended = InstructionSet::extended();
+        let program = assemble(source, &extended).unwrap();
+        assert_eq!(
+            "set a +3\nout a\nadd a -1\njnz a -2\nmul acc +2\nacc +7\n",
+            program.to_string()
+        );
+        assert_eq!(
+            Ok(program.clone()),
+            assemble(&program.to_string(), &extended)
+        );
+
+        // revisiting an address isn't a loop once registers can change where the program goes
+        let mut machine = Machine::new(&program);
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(vec![3, 2, 1], machine.output());
+        assert_eq!(7, machine.state().acc);
+
+        let mut machine = Machine::new(&assemble("set a 1\njnz a +0", &extended).unwrap());
+        assert_eq!(Outcome::Looped { pc: 1 }, machine.run());
+
+        let program = assemble("set a 2\nadd a -1\njnz a -1\njmp +0\nacc +5", &extended);
+        assert_eq!(Ok((3, 5)), fix_corruption_and_run(&program.unwrap()));
+
+        // the nop runs twice on the way into the loop, but it's still only one repair
+        let program = assemble("set a +2\nnop +4\nadd a -1\njnz a -2\njz d +0", &extended);
+        assert_eq!(Ok((1, 0)), fix_corruption_and_run(&program.unwrap()));
+    }
+
+    #[derive(Debug)]
+    struct Double;
+
+    impl fmt::Display for Double {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "dbl")
+        }
+    }
+
+    impl Operation for Double {
+        fn execute(&self, state: &mut MachineState) -> Effect {
+            state.acc *= 2;
+            Effect::next()
+        }
+    }
+
+    #[test]
+    fn custom_instructions() {
+        let mut instruction_set = InstructionSet::handheld();
+        instruction_set.register("dbl", |operands| match operands {
+            [] => Some(Instruction::Extended(Rc::new(Double))),
+            _ => None,
+        });
+        let program = assemble("acc +3\ndbl\ndbl", &instruction_set).unwrap();
+
+        let mut machine = Machine::new(&program);
+        assert_eq!(Outcome::Terminated, machine.run());
+        assert_eq!(12, machine.state().acc);
+        assert!(assemble("dbl +1", &instruction_set).is_err());
+
+        assert_eq!(program.instructions[1], program.instructions[2]);
+        assert_ne!(program.instructions[0], program.instructions[1]);
+        assert_ne!(Instruction::Acc { value: 1 }, Instruction::Nop { value: 1 });
+    }
+
+    #[test]
+    fn disassemble_example() {
+        let listing = disassemble(&Program::from(EXAMPLE));
+
+        assert_eq!(
+            r#"    nop +0      #    0: reachable
+L1:
+    acc +1      #    1: reachable, loop
+    jmp L6      #    2: reachable, loop
+L3:
+    acc +3      #    3: reachable, loop
+    jmp L1      #    4: reachable, loop
+    acc -99     #    5: unreachable
+L6:
+    acc +1      #    6: reachable, loop
+    jmp L3      #    7: reachable, loop
+    acc +6      #    8: unreachable
+"#,
+            listing
+        );
+    }
+
+    #[test]
+    fn tools_arguments() {
+        let program = "src/assets/day_08_input.txt";
 
-        assert_eq!(1532, program.read_acc())
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["trace"])).starts_with("Usage:"));
+        assert!(tools(&args(&["asm", program, "extra"])).starts_with("Usage:"));
+        assert!(tools(&args(&["run", program, "lots"])).starts_with("Usage:"));
+        assert!(tools(&args(&["run"])).starts_with("Outcome: Looped"));
+        assert_eq!(
+            "Outcome: BudgetExhausted\npc: 1\nacc: 14\ncycles: 1",
+            tools(&args(&["run", program, "1"]))
+        );
+        assert_eq!("Patched address 195\nacc: 1532", tools(&args(&["repair"])));
+        assert!(tools(&args(&["disasm", "no/such/file"])).starts_with("no/such/file: "));
     }
 }
@@ -1,46 +1,151 @@
-use std::collections::HashSet;
+use crate::tools::read_input;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, BufRead},
+};
 
 static INPUT: &'static str = include_str!("assets/day_09_input.txt");
 
-fn has_sum(lookback: &[i64], target: i64) -> bool {
-    let lookback_set: HashSet<&i64> = lookback.iter().collect();
-    for value in lookback_set.iter() {
-        let matching_value = target - *value;
-        if lookback_set.contains(&matching_value) {
-            return true;
+const PREAMBLE_LENGTH: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InvalidValue {
+    position: usize,
+    value: i64,
+}
+
+// Checks numbers one at a time against the previous `preamble` numbers. The window keeps a
+// count of each value in it, so moving along only updates the two values entering and leaving.
+#[derive(Debug)]
+struct Validator {
+    preamble: usize,
+    window: VecDeque<i64>,
+    counts: HashMap<i64, usize>,
+    position: usize,
+}
+
+impl Validator {
+    fn new(preamble: usize) -> Self {
+        Self {
+            preamble,
+            window: VecDeque::with_capacity(preamble + 1),
+            counts: HashMap::new(),
+            position: 0,
         }
     }
-    false
-}
 
-fn find_first_invalid_value(sequence: &Vec<i64>, lookback: usize) -> i64 {
-    let mut index = lookback;
-    while index < sequence.len() {
-        let lookback_sequence = &sequence[(index - lookback)..index];
-        if !has_sum(lookback_sequence, sequence[index]) {
-            return sequence[index];
+    // Whether two different values in the window add up to `target`
+    fn has_sum(&self, target: i64) -> bool {
+        self.counts.keys().any(|&value| {
+            let other = target - value;
+            other != value && self.counts.contains_key(&other)
+        })
+    }
+
+    // Returns the value back if it's invalid; values in the preamble are always valid
+    fn push(&mut self, value: i64) -> Option<InvalidValue> {
+        let position = self.position;
+        self.position += 1;
+        let invalid = if self.window.len() == self.preamble && !self.has_sum(value) {
+            Some(InvalidValue { position, value })
+        } else {
+            None
+        };
+
+        self.window.push_back(value);
+        *self.counts.entry(value).or_insert(0) += 1;
+        if self.window.len() > self.preamble {
+            let oldest = self.window.pop_front().unwrap();
+            let count = self.counts.get_mut(&oldest).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&oldest);
+            }
         }
-        index += 1;
+        invalid
     }
-    panic!("no invalid values");
 }
 
-fn find_encryption_weakness(sequence: &Vec<i64>, target_sum: i64) -> i64 {
-    let mut min_ptr = 0;
-    let mut max_ptr = 1;
-    loop {
-        let range = &sequence[min_ptr..max_ptr];
-        let sum: i64 = range.iter().sum();
-        if sum == target_sum {
-            return range.iter().min().unwrap() + range.iter().max().unwrap();
-        }
-        if sum > target_sum {
-            min_ptr += 1;
+fn find_invalid_values(
+    sequence: impl IntoIterator<Item = i64>,
+    preamble: usize,
+) -> impl Iterator<Item = InvalidValue> {
+    let mut validator = Validator::new(preamble);
+    sequence
+        .into_iter()
+        .filter_map(move |value| validator.push(value))
+}
+
+fn find_first_invalid_value(sequence: &[i64], preamble: usize) -> Option<i64> {
+    find_invalid_values(sequence.iter().copied(), preamble)
+        .next()
+        .map(|invalid| invalid.value)
+}
+
+// Validates one number per line as it's read, stopping at the first line that isn't a number
+fn validate_stream(reader: impl BufRead, preamble: usize) -> Result<Vec<InvalidValue>, String> {
+    let mut validator = Validator::new(preamble);
+    let mut invalid_values = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
         }
-        if sum < target_sum {
-            max_ptr += 1;
+        let value = line
+            .trim()
+            .parse()
+            .map_err(|_| format!("line {}: invalid number '{}'", index + 1, line))?;
+        invalid_values.extend(validator.push(value));
+    }
+    Ok(invalid_values)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WeakRange {
+    start: usize,
+    // exclusive
+    end: usize,
+    // smallest plus largest value in the range
+    weakness: i64,
+}
+
+// Every range of at least two contiguous values adding up to `target`, ordered by where they
+// start. A range sums to `target` when the prefix sum at its end is `target` more than the
+// prefix sum at its start, so each end looks up the starts that match.
+fn find_weak_ranges(sequence: &[i64], target: i64) -> Vec<WeakRange> {
+    let mut prefix_sums = vec![0; sequence.len() + 1];
+    for (index, value) in sequence.iter().enumerate() {
+        prefix_sums[index + 1] = prefix_sums[index] + value;
+    }
+
+    let mut starts: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut ranges = Vec::new();
+    for end in 2..=sequence.len() {
+        // only starts at least two values back are long enough
+        starts
+            .entry(prefix_sums[end - 2])
+            .or_default()
+            .push(end - 2);
+        if let Some(matching) = starts.get(&(prefix_sums[end] - target)) {
+            for &start in matching {
+                let range = &sequence[start..end];
+                ranges.push(WeakRange {
+                    start,
+                    end,
+                    weakness: range.iter().min().unwrap() + range.iter().max().unwrap(),
+                });
+            }
         }
     }
+    ranges.sort_by_key(|range| (range.start, range.end));
+    ranges
+}
+
+fn find_encryption_weakness(sequence: &[i64], target_sum: i64) -> Option<i64> {
+    find_weak_ranges(sequence, target_sum)
+        .first()
+        .map(|range| range.weakness)
 }
 
 fn parse_input(input: &str) -> Vec<i64> {
@@ -49,18 +154,90 @@ fn parse_input(input: &str) -> Vec<i64> {
 
 pub fn p1() -> i64 {
     let sequence = parse_input(INPUT);
-    find_first_invalid_value(&sequence, 25)
+    find_first_invalid_value(&sequence, PREAMBLE_LENGTH).expect("no invalid values")
 }
 
 pub fn p2() -> i64 {
     let sequence = parse_input(INPUT);
-    let invalid_value = find_first_invalid_value(&sequence, 25);
-    find_encryption_weakness(&sequence, invalid_value)
+    let invalid_value =
+        find_first_invalid_value(&sequence, PREAMBLE_LENGTH).expect("no invalid values");
+    find_encryption_weakness(&sequence, invalid_value).expect("no weak range")
+}
+
+fn load_numbers(path: Option<&String>) -> Result<Vec<i64>, String> {
+    let input = read_input(path, INPUT)?;
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            line.trim()
+                .parse()
+                .map_err(|_| format!("line {}: invalid number '{}'", index + 1, line))
+        })
+        .collect()
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 9 validate [preamble] [numbers|-]\n\tadvent2020 9 ranges <target> [numbers]";
+    let result = match args {
+        [command, rest @ ..] if command == "validate" && rest.len() <= 2 => {
+            let preamble = match rest
+                .first()
+                .map_or(Ok(PREAMBLE_LENGTH), |preamble| preamble.parse())
+            {
+                Ok(preamble) => preamble,
+                Err(_) => return usage.to_string(),
+            };
+            let invalid_values = match rest.get(1).map(String::as_str) {
+                Some("-") => validate_stream(io::stdin().lock(), preamble),
+                Some(path) => fs::File::open(path)
+                    .map_err(|err| format!("{}: {}", path, err))
+                    .and_then(|file| validate_stream(io::BufReader::new(file), preamble)),
+                None => validate_stream(INPUT.as_bytes(), preamble),
+            };
+            invalid_values.map(|invalid_values| {
+                if invalid_values.is_empty() {
+                    return "All values valid".to_string();
+                }
+                invalid_values
+                    .iter()
+                    .map(|invalid| format!("{}: {}", invalid.position, invalid.value))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        [command, target, rest @ ..] if command == "ranges" && rest.len() <= 1 => {
+            let target = match target.parse() {
+                Ok(target) => target,
+                Err(_) => return usage.to_string(),
+            };
+            load_numbers(rest.first()).map(|sequence| {
+                let ranges = find_weak_ranges(&sequence, target);
+                if ranges.is_empty() {
+                    return "No ranges found".to_string();
+                }
+                ranges
+                    .iter()
+                    .map(|range| {
+                        format!(
+                            "{}..{}: weakness {}",
+                            range.start, range.end, range.weakness
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        _ => Ok(usage.to_string()),
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"35
 20
@@ -87,27 +264,103 @@ mod test {
     fn p1_example() {
         let sequence = parse_input(EXAMPLE);
 
-        assert_eq!(127, find_first_invalid_value(&sequence, 5));
+        assert_eq!(Some(127), find_first_invalid_value(&sequence, 5));
     }
 
     #[test]
     fn p1_correct_answer() {
         let sequence = parse_input(INPUT);
 
-        assert_eq!(2089807806, find_first_invalid_value(&sequence, 25))
+        assert_eq!(
+            Some(2089807806),
+            find_first_invalid_value(&sequence, PREAMBLE_LENGTH)
+        )
     }
 
     #[test]
     fn p2_example() {
         let sequence = parse_input(EXAMPLE);
 
-        assert_eq!(62, find_encryption_weakness(&sequence, 127));
+        assert_eq!(Some(62), find_encryption_weakness(&sequence, 127));
     }
 
     #[test]
     fn p2_correct_answer() {
         let sequence = parse_input(INPUT);
 
-        assert_eq!(245848639, find_encryption_weakness(&sequence, 2089807806))
+        assert_eq!(
+            Some(245848639),
+            find_encryption_weakness(&sequence, 2089807806)
+        )
+    }
+
+    #[test]
+    fn every_invalid_value() {
+        let sequence = parse_input(EXAMPLE);
+        let invalid_values: Vec<InvalidValue> =
+            find_invalid_values(sequence.iter().copied(), 5).collect();
+
+        assert_eq!(
+            vec![InvalidValue {
+                position: 14,
+                value: 127
+            }],
+            invalid_values
+        );
+        assert_eq!(None, find_first_invalid_value(&sequence[..14], 5));
+        // equal values don't count as a pair
+        assert_eq!(Some(2), find_first_invalid_value(&[1, 1, 2], 2));
+        assert_eq!(Some(3), find_first_invalid_value(&[1, 2, 3, 3, 6], 2));
+    }
+
+    #[test]
+    fn validate_stream_example() {
+        let invalid_values = validate_stream(EXAMPLE.as_bytes(), 5).unwrap();
+        assert_eq!(
+            find_invalid_values(parse_input(EXAMPLE), 5).collect::<Vec<_>>(),
+            invalid_values
+        );
+        assert_eq!(
+            Err("line 2: invalid number 'x'".to_string()),
+            validate_stream("1\nx\n3".as_bytes(), 1)
+        );
+    }
+
+    #[test]
+    fn every_weak_range() {
+        let sequence = parse_input(EXAMPLE);
+        assert_eq!(
+            vec![WeakRange {
+                start: 2,
+                end: 6,
+                weakness: 62
+            }],
+            find_weak_ranges(&sequence, 127)
+        );
+
+        // a single value isn't a range
+        assert_eq!(None, find_encryption_weakness(&sequence, 47));
+        assert_eq!(None, find_encryption_weakness(&sequence, 1));
+        assert_eq!(Some(55), find_encryption_weakness(&sequence, 55));
+
+        let ranges = find_weak_ranges(&[1, 2, 3, 0, 3, -3, 3], 3);
+        let bounds: Vec<(usize, usize)> = ranges.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(vec![(0, 2), (2, 4), (2, 6), (3, 5), (3, 7), (4, 7)], bounds);
+        assert!(find_weak_ranges(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["validate", "many"])).starts_with("Usage:"));
+        assert!(tools(&args(&["ranges", "lots"])).starts_with("Usage:"));
+        assert!(tools(&args(&["validate"])).contains(": 2089807806"));
+        assert!(tools(&args(&["ranges", "2089807806"])).contains(": weakness 245848639"));
+        assert!(
+            tools(&args(&["validate", "25", "no/such/file"])).starts_with("Error: no/such/file: ")
+        );
+        assert!(
+            tools(&args(&["ranges", "127", "no/such/file"])).starts_with("Error: no/such/file: ")
+        );
     }
 }
@@ -1,56 +1,267 @@
+use crate::tools::read_input;
+use std::{collections::BTreeMap, fmt, iter::Sum, ops::AddAssign, str::FromStr};
+
 static INPUT: &'static str = include_str!("assets/day_10_input.txt");
 
-fn order_adpaters(adapters: &mut Vec<i32>) {
-    adapters.sort();
-    adapters.insert(0, 0);
-    adapters.push(adapters[adapters.len() - 1] + 3);
+const LIMB_BASE: u32 = 1_000_000_000;
+
+// Unsigned integer of any size, stored as base 10^9 limbs with the least significant first.
+// Only supports what counting arrangements needs: addition and printing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct BigUint {
+    limbs: Vec<u32>,
 }
 
-fn find_joltage_differences(mut adapters: Vec<i32>) -> usize {
-    let (mut diff1, mut diff3) = (0, 0);
-    order_adpaters(&mut adapters);
+impl BigUint {
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
 
-    for i in 1..adapters.len() {
-        let (previous, current) = (adapters[i - 1], adapters[i]);
-        match current - previous {
-            1 => {
-                diff1 += 1;
+    fn to_u64(&self) -> Option<u64> {
+        self.limbs.iter().rev().try_fold(0u64, |value, &limb| {
+            value
+                .checked_mul(LIMB_BASE as u64)?
+                .checked_add(limb as u64)
+        })
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(mut value: u64) -> Self {
+        let mut limbs = Vec::new();
+        while value > 0 {
+            limbs.push((value % LIMB_BASE as u64) as u32);
+            value /= LIMB_BASE as u64;
+        }
+        Self { limbs }
+    }
+}
+
+impl AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0;
+        for index in 0..self.limbs.len().max(other.limbs.len()) {
+            if index == self.limbs.len() {
+                self.limbs.push(0);
             }
-            3 => {
-                diff3 += 1;
+            let sum = self.limbs[index] + other.limbs.get(index).copied().unwrap_or(0) + carry;
+            self.limbs[index] = sum % LIMB_BASE;
+            carry = sum / LIMB_BASE;
+        }
+        if carry > 0 {
+            self.limbs.push(carry);
+        }
+    }
+}
+
+impl<'a> Sum<&'a BigUint> for BigUint {
+    fn sum<I: Iterator<Item = &'a BigUint>>(iter: I) -> Self {
+        iter.fold(BigUint::default(), |mut total, value| {
+            total += value;
+            total
+        })
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limbs.split_last() {
+            None => write!(f, "0"),
+            Some((most_significant, rest)) => {
+                write!(f, "{}", most_significant)?;
+                for limb in rest.iter().rev() {
+                    write!(f, "{:09}", limb)?;
+                }
+                Ok(())
             }
-            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChainRules {
+    // an adapter accepts input from `min_step` to `max_step` jolts lower than its rating
+    min_step: i32,
+    max_step: i32,
+    // the device is rated this much higher than the highest adapter
+    device_offset: i32,
+}
+
+impl Default for ChainRules {
+    fn default() -> Self {
+        Self {
+            min_step: 1,
+            max_step: 3,
+            device_offset: 3,
+        }
+    }
+}
+
+impl ChainRules {
+    fn allows(&self, step: i32) -> bool {
+        (self.min_step..=self.max_step).contains(&step)
+    }
+}
+
+// Parses a tolerance like `1..=3` and a device offset like `3`
+impl FromStr for ChainRules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let (tolerance, device_offset) = match tokens[..] {
+            [tolerance, device_offset] => (tolerance, device_offset),
+            _ => {
+                return Err(format!(
+                    "expected a tolerance and device offset, got '{}'",
+                    s
+                ))
+            }
+        };
+        let (min_step, max_step) = match tolerance.split_once("..=") {
+            Some((min, max)) => match (min.parse(), max.parse()) {
+                (Ok(min), Ok(max)) if min <= max => (min, max),
+                _ => return Err(format!("invalid tolerance '{}'", tolerance)),
+            },
+            None => return Err(format!("expected a range like 1..=3, got '{}'", tolerance)),
         };
+        let device_offset = device_offset
+            .parse()
+            .map_err(|_| format!("invalid device offset '{}'", device_offset))?;
+        Ok(Self {
+            min_step,
+            max_step,
+            device_offset,
+        })
     }
+}
 
-    diff1 * diff3
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ChainAnalysis {
+    // the outlet, every adapter in order, then the device
+    joltages: Vec<i32>,
+    // how often each difference appears when every adapter is used
+    differences: BTreeMap<i32, usize>,
+    // steps outside the tolerance when every adapter is used, as (from, to) joltages
+    invalid_steps: Vec<(i32, i32)>,
+    // ways to get from the outlet to the device with any subset of the adapters
+    arrangements: BigUint,
+    rules: ChainRules,
 }
 
-fn resolve_sequence(adapters: &Vec<i32>, start_index: usize, cache: &mut Vec<u64>) -> u64 {
-    // base case, we've found a complete sequence
-    if start_index == adapters.len() - 1 {
-        return 1;
+impl ChainAnalysis {
+    fn uses_every_adapter(&self) -> bool {
+        self.invalid_steps.is_empty()
+    }
+
+    fn reaches_device(&self) -> bool {
+        !self.arrangements.is_zero()
     }
-    let mut possible_sequences = 0;
-    let last_index = std::cmp::min(start_index + 3, adapters.len() - 1);
-    for i in (start_index + 1)..=last_index {
-        if adapters[i] - adapters[start_index] > 3 {
-            break;
+
+    fn chains(&self) -> Chains<'_> {
+        Chains {
+            joltages: &self.joltages,
+            rules: self.rules,
+            path: vec![(0, 1)],
+        }
+    }
+
+    // How many chains use each number of adapters. This enumerates every chain, so it's only
+    // practical for small bags.
+    fn chain_lengths(&self) -> BTreeMap<usize, usize> {
+        let mut lengths = BTreeMap::new();
+        for chain in self.chains() {
+            *lengths.entry(chain.len() - 2).or_insert(0) += 1;
+        }
+        lengths
+    }
+}
+
+// Lazily walks every valid chain from the outlet to the device, depth first, yielding the
+// joltages along each one (outlet and device included)
+#[derive(Debug)]
+struct Chains<'a> {
+    joltages: &'a [i32],
+    rules: ChainRules,
+    // indices of the joltages in the chain so far, each with the next index to try after it
+    path: Vec<(usize, usize)>,
+}
+
+impl Iterator for Chains<'_> {
+    type Item = Vec<i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let device = self.joltages.len() - 1;
+        loop {
+            let (index, next) = *self.path.last()?;
+            if next > device || self.joltages[next] - self.joltages[index] > self.rules.max_step {
+                self.path.pop();
+                continue;
+            }
+            self.path.last_mut().unwrap().1 += 1;
+            if self.joltages[next] - self.joltages[index] < self.rules.min_step {
+                continue;
+            }
+            if next == device {
+                let mut chain: Vec<i32> = self
+                    .path
+                    .iter()
+                    .map(|&(index, _)| self.joltages[index])
+                    .collect();
+                chain.push(self.joltages[device]);
+                return Some(chain);
+            }
+            self.path.push((next, next + 1));
         }
-        if cache[i] == 0 {
-            cache[i] = resolve_sequence(adapters, i, cache);
+    }
+}
+
+fn analyze_chain(adapters: &[i32], rules: &ChainRules) -> ChainAnalysis {
+    let mut joltages = adapters.to_vec();
+    joltages.sort_unstable();
+    joltages.insert(0, 0);
+    joltages.push(joltages[joltages.len() - 1] + rules.device_offset);
+
+    let mut differences = BTreeMap::new();
+    let mut invalid_steps = Vec::new();
+    for pair in joltages.windows(2) {
+        let step = pair[1] - pair[0];
+        *differences.entry(step).or_insert(0) += 1;
+        if !rules.allows(step) {
+            invalid_steps.push((pair[0], pair[1]));
         }
-        possible_sequences += cache[i];
     }
 
-    possible_sequences
+    // ways[i] counts the arrangements ending at joltages[i], built up from the outlet so a
+    // genuine count of zero is just another count
+    let mut ways = vec![BigUint::default(); joltages.len()];
+    ways[0] = BigUint::from(1);
+    for i in 1..joltages.len() {
+        ways[i] = (0..i)
+            .rev()
+            .take_while(|&j| joltages[i] - joltages[j] <= rules.max_step)
+            .filter(|&j| joltages[i] - joltages[j] >= rules.min_step)
+            .map(|j| &ways[j])
+            .sum();
+    }
+
+    ChainAnalysis {
+        arrangements: ways.pop().unwrap(),
+        rules: *rules,
+        joltages,
+        differences,
+        invalid_steps,
+    }
+}
+
+fn find_joltage_differences(adapters: Vec<i32>) -> usize {
+    let analysis = analyze_chain(&adapters, &ChainRules::default());
+    let count = |step| analysis.differences.get(&step).copied().unwrap_or(0);
+    count(1) * count(3)
 }
 
-fn find_all_sequences(adapters: Vec<i32>) -> u64 {
-    let mut adapters = adapters;
-    order_adpaters(&mut adapters);
-    let mut cache: Vec<u64> = vec![0; adapters.len()];
-    resolve_sequence(&adapters, 0, &mut cache)
+fn find_all_sequences(adapters: Vec<i32>) -> BigUint {
+    analyze_chain(&adapters, &ChainRules::default()).arrangements
 }
 
 fn parse_input(input: &str) -> Vec<i32> {
@@ -61,13 +272,114 @@ pub fn p1() -> usize {
     find_joltage_differences(parse_input(INPUT))
 }
 
-pub fn p2() -> u64 {
+pub fn p2() -> impl fmt::Display {
     find_all_sequences(parse_input(INPUT))
 }
 
+// Counting chains by length enumerates them all, so refuse bags with more than this many
+const MAX_ENUMERATED_CHAINS: u64 = 1_000_000;
+
+fn load_adapters(path: Option<&String>) -> Result<Vec<i32>, String> {
+    let input = read_input(path, INPUT)?;
+    input
+        .split_whitespace()
+        .map(|adapter| {
+            adapter
+                .parse()
+                .map_err(|_| format!("invalid adapter '{}'", adapter))
+        })
+        .collect()
+}
+
+fn report_analysis(analysis: &ChainAnalysis) -> String {
+    let mut report: Vec<String> = analysis
+        .differences
+        .iter()
+        .map(|(step, count)| format!("{} jolt steps: {}", step, count))
+        .collect();
+    if !analysis.uses_every_adapter() {
+        let steps: Vec<String> = analysis
+            .invalid_steps
+            .iter()
+            .map(|(from, to)| format!("{} -> {}", from, to))
+            .collect();
+        report.push(format!("Can't chain every adapter: {}", steps.join(", ")));
+    }
+    if analysis.reaches_device() {
+        report.push(format!("Arrangements: {}", analysis.arrangements));
+    } else {
+        report.push("No arrangement reaches the device".to_string());
+    }
+    report.join("\n")
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 10 analyze [<min>..=<max> <device offset>] [adapters]\n\tadvent2020 10 chains <count> [adapters]\n\tadvent2020 10 lengths [adapters]";
+    let result = match args {
+        [command, rest @ ..] if command == "analyze" && rest.len() <= 3 => {
+            let (rules, path) = match rest {
+                [tolerance, device_offset, path @ ..] if tolerance.contains("..=") => {
+                    match format!("{} {}", tolerance, device_offset).parse() {
+                        Ok(rules) => (rules, path.first()),
+                        Err(err) => return format!("Error: {}", err),
+                    }
+                }
+                [path] => (ChainRules::default(), Some(path)),
+                [] => (ChainRules::default(), None),
+                _ => return usage.to_string(),
+            };
+            load_adapters(path).map(|adapters| report_analysis(&analyze_chain(&adapters, &rules)))
+        }
+        [command, count, rest @ ..] if command == "chains" && rest.len() <= 1 => {
+            let count = match count.parse() {
+                Ok(count) => count,
+                Err(_) => return usage.to_string(),
+            };
+            load_adapters(rest.first()).map(|adapters| {
+                analyze_chain(&adapters, &ChainRules::default())
+                    .chains()
+                    .take(count)
+                    .map(|chain| {
+                        chain
+                            .iter()
+                            .map(i32::to_string)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+        }
+        [command, rest @ ..] if command == "lengths" && rest.len() <= 1 => {
+            load_adapters(rest.first()).and_then(|adapters| {
+                let analysis = analyze_chain(&adapters, &ChainRules::default());
+                if analysis
+                    .arrangements
+                    .to_u64()
+                    .is_none_or(|arrangements| arrangements > MAX_ENUMERATED_CHAINS)
+                {
+                    return Err(format!(
+                        "{} chains is too many to enumerate",
+                        analysis.arrangements
+                    ));
+                }
+                Ok(analysis
+                    .chain_lengths()
+                    .iter()
+                    .map(|(length, count)| format!("{} adapters: {}", length, count))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            })
+        }
+        _ => Ok(usage.to_string()),
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE1: &str = r#"16
 10
@@ -134,14 +446,101 @@ mod test {
         let ex1 = parse_input(EXAMPLE1);
         let ex2 = parse_input(EXAMPLE2);
 
-        assert_eq!(8, find_all_sequences(ex1));
-        assert_eq!(19208, find_all_sequences(ex2));
+        assert_eq!(BigUint::from(8), find_all_sequences(ex1));
+        assert_eq!(BigUint::from(19208), find_all_sequences(ex2));
     }
 
     #[test]
     fn p2_correct_answer() {
         let adapters = parse_input(INPUT);
 
-        assert_eq!(3454189699072, find_all_sequences(adapters));
+        assert_eq!(BigUint::from(3454189699072), find_all_sequences(adapters));
+    }
+
+    #[test]
+    fn analyze_chain_example() {
+        let analysis = analyze_chain(&parse_input(EXAMPLE1), &ChainRules::default());
+
+        assert_eq!(btreemap! {1 => 7, 3 => 5}, analysis.differences);
+        assert!(analysis.uses_every_adapter());
+        assert_eq!(22, analysis.joltages[analysis.joltages.len() - 1]);
+        assert_eq!(BigUint::from(8), analysis.arrangements);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules: ChainRules = "1..=1 2".parse().unwrap();
+        let analysis = analyze_chain(&[1, 2, 4], &rules);
+
+        assert_eq!(vec![0, 1, 2, 4, 6], analysis.joltages);
+        assert_eq!(vec![(2, 4), (4, 6)], analysis.invalid_steps);
+        assert!(!analysis.reaches_device());
+
+        let rules: ChainRules = "2..=3 3".parse().unwrap();
+        let analysis = analyze_chain(&[1, 2, 3, 5], &rules);
+        assert!(!analysis.uses_every_adapter());
+        // 0-2-5-8 and 0-3-5-8
+        assert_eq!(BigUint::from(2), analysis.arrangements);
+
+        assert!("3..=1 3".parse::<ChainRules>().is_err());
+        assert!("1..=3".parse::<ChainRules>().is_err());
+    }
+
+    #[test]
+    fn big_uint() {
+        let mut value = BigUint::from(999_999_999);
+        value += &BigUint::from(1);
+        assert_eq!("1000000000", value.to_string());
+        assert_eq!("18446744073709551615", BigUint::from(u64::MAX).to_string());
+        assert_eq!("0", BigUint::default().to_string());
+        assert_eq!(Some(u64::MAX), BigUint::from(u64::MAX).to_u64());
+
+        let mut doubled = BigUint::from(u64::MAX);
+        doubled += &BigUint::from(u64::MAX);
+        assert_eq!("36893488147419103230", doubled.to_string());
+        assert_eq!(None, doubled.to_u64());
+    }
+
+    #[test]
+    fn arrangements_beyond_u64() {
+        let adapters: Vec<i32> = (1..=200).collect();
+
+        assert_eq!(
+            "52622583840983769603765180599790256716084480555530641",
+            find_all_sequences(adapters).to_string()
+        );
+    }
+
+    #[test]
+    fn enumerate_chains() {
+        let analysis = analyze_chain(&parse_input(EXAMPLE1), &ChainRules::default());
+        let chains: Vec<Vec<i32>> = analysis.chains().collect();
+
+        assert_eq!(8, chains.len());
+        assert_eq!(
+            vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22],
+            chains[0]
+        );
+        assert_eq!(vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22], chains[7]);
+        assert_eq!(
+            btreemap! {8 => 1, 9 => 3, 10 => 3, 11 => 1},
+            analysis.chain_lengths()
+        );
+
+        let analysis = analyze_chain(&[1, 5], &ChainRules::default());
+        assert_eq!(0, analysis.chains().count());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["chains", "some"])).starts_with("Usage:"));
+        assert!(tools(&args(&["analyze", "1..=3"])).starts_with("Error: "));
+        assert_eq!(
+            "Error: 3454189699072 chains is too many to enumerate",
+            tools(&args(&["lengths"]))
+        );
+        assert_eq!(2, tools(&args(&["chains", "2"])).lines().count());
+        assert!(tools(&args(&["analyze", "no/such/file"])).starts_with("Error: no/such/file: "));
     }
 }
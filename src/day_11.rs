@@ -1,4 +1,10 @@
-use std::{collections::HashMap, fmt};
+use crate::tools::read_input;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 
 static INPUT: &'static str = include_str!("assets/day_11_input.txt");
 
@@ -16,6 +22,99 @@ lazy_static! {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+enum Neighborhood {
+    // the cells at these offsets
+    Offsets(Vec<(i32, i32)>),
+    // the first seat seen looking in each of these directions
+    LineOfSight(Vec<(i32, i32)>),
+}
+
+// `adjacent`, `visible`, or `offsets:`/`sight:` followed by `dx,dy` pairs separated by `;`
+impl FromStr for Neighborhood {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_offsets = |offsets: &str| {
+            offsets
+                .split(';')
+                .map(|offset| match offset.split_once(',') {
+                    Some((dx, dy)) => match (dx.trim().parse(), dy.trim().parse()) {
+                        (Ok(dx), Ok(dy)) if (dx, dy) != (0, 0) => Ok((dx, dy)),
+                        _ => Err(format!("invalid offset '{}'", offset)),
+                    },
+                    None => Err(format!("expected an offset like 1,-1, got '{}'", offset)),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match s.split_once(':') {
+            None if s == "adjacent" => Ok(Neighborhood::Offsets(ADJACENT.clone())),
+            None if s == "visible" => Ok(Neighborhood::LineOfSight(ADJACENT.clone())),
+            Some(("offsets", offsets)) => parse_offsets(offsets).map(Neighborhood::Offsets),
+            Some(("sight", offsets)) => parse_offsets(offsets).map(Neighborhood::LineOfSight),
+            _ => Err(format!("unknown neighborhood '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Boundary {
+    // nothing beyond the edge of the grid
+    Edge,
+    // the grid repeats, so looking off one side continues from the other
+    Wrap,
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "edge" => Ok(Boundary::Edge),
+            "wrap" => Ok(Boundary::Wrap),
+            _ => Err(format!("unknown boundary '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rules {
+    neighborhood: Neighborhood,
+    // an empty seat is taken when at most this many neighbors are occupied
+    occupy_at_most: usize,
+    // an occupied seat is vacated when at least this many neighbors are occupied
+    vacate_at_least: usize,
+    boundary: Boundary,
+}
+
+impl Rules {
+    fn adjacent() -> Self {
+        Self {
+            neighborhood: Neighborhood::Offsets(ADJACENT.clone()),
+            occupy_at_most: 0,
+            vacate_at_least: 4,
+            boundary: Boundary::Edge,
+        }
+    }
+
+    fn visible() -> Self {
+        Self {
+            neighborhood: Neighborhood::LineOfSight(ADJACENT.clone()),
+            occupy_at_most: 0,
+            vacate_at_least: 5,
+            boundary: Boundary::Edge,
+        }
+    }
+
+    fn next_seat(&self, seat: Seat, occupied_neighbors: usize) -> Seat {
+        match seat {
+            Seat::Empty if occupied_neighbors <= self.occupy_at_most => Seat::Occupied,
+            Seat::Occupied if occupied_neighbors >= self.vacate_at_least => Seat::Empty,
+            _ => seat,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Seat {
     Occupied,
     Empty,
@@ -78,143 +177,297 @@ impl SeatGrid {
         }
     }
 
-    fn find_visible(&self, x: i32, y: i32, dir: &(i32, i32)) -> Option<usize> {
-        let mut mult = 1;
-        while mult < self.width && mult < self.height {
-            let (target_x, target_y) = (x + mult * dir.0, y + mult * dir.1);
-            if target_x < 0 || target_y < 0 || target_x >= self.width || target_y >= self.height {
+    // The cell `steps` moves of `offset` away, if it's on the grid
+    fn cell_at(
+        &self,
+        x: i32,
+        y: i32,
+        offset: (i32, i32),
+        steps: i32,
+        boundary: Boundary,
+    ) -> Option<usize> {
+        let (mut target_x, mut target_y) = (x + steps * offset.0, y + steps * offset.1);
+        if boundary == Boundary::Wrap {
+            target_x = target_x.rem_euclid(self.width);
+            target_y = target_y.rem_euclid(self.height);
+        }
+        if target_x < 0 || target_y < 0 || target_x >= self.width || target_y >= self.height {
+            return None;
+        }
+        Some((target_y * self.width + target_x) as usize)
+    }
+
+    fn find_visible(&self, x: i32, y: i32, dir: (i32, i32), boundary: Boundary) -> Option<usize> {
+        let start = (y * self.width + x) as usize;
+        // wrapping rays come back round eventually, so give up after crossing every cell
+        for steps in 1..=self.width * self.height {
+            let target = self.cell_at(x, y, dir, steps, boundary)?;
+            if target == start {
                 break;
             }
-            let target_index = target_y * self.width + target_x;
-            if self.seats[target_index as usize].is_some() {
-                return Some(target_index as usize);
+            if self.seats[target].is_some() {
+                return Some(target);
             }
-            mult += 1;
         }
-
         None
     }
 
-    fn get_visibility_graph(&self) -> HashMap<usize, Vec<usize>> {
-        let mut result = HashMap::new();
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let index = (y * self.width + x) as usize;
-                let visible = ADJACENT
-                    .iter()
-                    .filter_map(|dir| self.find_visible(x, y, dir))
-                    .collect();
-                result.insert(index, visible);
+    // The seats each cell's neighborhood covers, by index. On grids too small for wrapping to
+    // keep offsets apart, several can land on the same seat, or back on the cell itself; each
+    // seat still only counts once, and never as its own neighbor.
+    fn neighbors_of(&self, x: i32, y: i32, rules: &Rules) -> Vec<usize> {
+        let origin = (y * self.width + x) as usize;
+        let found: Vec<usize> = match &rules.neighborhood {
+            Neighborhood::Offsets(offsets) => offsets
+                .iter()
+                .filter_map(|&offset| self.cell_at(x, y, offset, 1, rules.boundary))
+                .filter(|&index| self.seats[index].is_some())
+                .collect(),
+            Neighborhood::LineOfSight(directions) => directions
+                .iter()
+                .filter_map(|&dir| self.find_visible(x, y, dir, rules.boundary))
+                .collect(),
+        };
+        let mut neighbors = Vec::with_capacity(found.len());
+        for index in found {
+            if index != origin && !neighbors.contains(&index) {
+                neighbors.push(index);
             }
         }
-        result
-    }
-
-    fn count_occupied_adjacent(&self, x: i32, y: i32) -> usize {
-        ADJACENT
-            .iter()
-            .filter_map(|&(other_x, other_y)| {
-                let (target_x, target_y) = (x + other_x, y + other_y);
-                if target_x < 0 || target_x >= self.width || target_y < 0 || target_y >= self.height
-                {
-                    None
-                } else {
-                    let index = target_y * self.width + target_x;
-                    self.seats[index as usize].as_ref()
-                }
-            })
-            .filter(|seat| seat == &&Seat::Occupied)
-            .count()
+        neighbors
     }
 
     fn total_occupied(&self) -> usize {
-        self.seats
-            .iter()
-            .filter(|&seat| seat == &Some(Seat::Occupied))
-            .count()
-    }
-
-    fn apply_round_adjacent(&self) -> Self {
-        let mut new_seats = Vec::new();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let index = (y * self.width + x) as usize;
-                let current = &self.seats[index];
-                if current.is_none() {
-                    new_seats.insert(index, current.to_owned());
-                    continue;
-                }
-                let adjacent_occupied = self.count_occupied_adjacent(x, y);
-                if current == &Some(Seat::Empty) && adjacent_occupied == 0 {
-                    new_seats.insert(index, Some(Seat::Occupied));
-                } else if current == &Some(Seat::Occupied) && adjacent_occupied >= 4 {
-                    new_seats.insert(index, Some(Seat::Empty));
-                } else {
-                    new_seats.insert(index, current.to_owned());
+        count_occupied(&self.seats, 0..self.seats.len())
+    }
+}
+
+fn count_occupied(seats: &[Option<Seat>], indices: impl IntoIterator<Item = usize>) -> usize {
+    indices
+        .into_iter()
+        .filter(|&index| seats[index] == Some(Seat::Occupied))
+        .count()
+}
+
+// Every cell's neighbors in one flat list: cell `i` has `neighbors[starts[i]..starts[i + 1]]`
+#[derive(Debug, Clone)]
+struct NeighborTable {
+    starts: Vec<usize>,
+    neighbors: Vec<usize>,
+}
+
+impl NeighborTable {
+    fn build(seat_grid: &SeatGrid, rules: &Rules) -> Self {
+        let mut starts = vec![0];
+        let mut neighbors = Vec::new();
+        for y in 0..seat_grid.height {
+            for x in 0..seat_grid.width {
+                // floor never changes, so it doesn't need any
+                if seat_grid.seats[(y * seat_grid.width + x) as usize].is_some() {
+                    neighbors.extend(seat_grid.neighbors_of(x, y, rules));
                 }
+                starts.push(neighbors.len());
+            }
+        }
+        Self { starts, neighbors }
+    }
+
+    // The cells that have each cell as a neighbor, which differ from its own neighbors when
+    // the neighborhood isn't symmetric
+    fn reversed(&self) -> Self {
+        let mut counts = vec![0; self.starts.len()];
+        for &neighbor in &self.neighbors {
+            counts[neighbor + 1] += 1;
+        }
+        let mut starts = counts;
+        for index in 1..starts.len() {
+            starts[index] += starts[index - 1];
+        }
+        let mut next = starts.clone();
+        let mut neighbors = vec![0; self.neighbors.len()];
+        for cell in 0..self.starts.len() - 1 {
+            for &neighbor in self.of(cell) {
+                neighbors[next[neighbor]] = cell;
+                next[neighbor] += 1;
             }
         }
+        Self { starts, neighbors }
+    }
+
+    fn of(&self, cell: usize) -> &[usize] {
+        &self.neighbors[self.starts[cell]..self.starts[cell + 1]]
+    }
+}
+
+// Seats that might change in the coming round, and which cells are already queued
+#[derive(Debug)]
+struct Worklist {
+    watchers: NeighborTable,
+    pending: Vec<usize>,
+    queued: Vec<bool>,
+    changes: Vec<(usize, Seat)>,
+}
 
+// Simulations give up after this many rounds unless told otherwise
+const MAX_ROUNDS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Settled {
+    // nothing changed in this round
+    Stable { round: usize },
+    // the grid after `round` is the same as it was `period` rounds earlier
+    Oscillating { round: usize, period: usize },
+    // still changing after this many rounds
+    Unsettled { rounds: usize },
+}
+
+// Runs rounds without allocating: the full simulation writes each round into a second buffer
+// and swaps, while the worklist version only revisits seats next to last round's changes and
+// updates the grid in place.
+#[derive(Debug)]
+struct Simulation<'a> {
+    rules: &'a Rules,
+    width: i32,
+    height: i32,
+    neighbors: NeighborTable,
+    current: Vec<Option<Seat>>,
+    next: Vec<Option<Seat>>,
+    worklist: Option<Worklist>,
+    rounds: usize,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(seat_grid: &SeatGrid, rules: &'a Rules) -> Self {
         Self {
-            width: self.width,
-            height: self.height,
-            seats: new_seats,
+            rules,
+            width: seat_grid.width,
+            height: seat_grid.height,
+            neighbors: NeighborTable::build(seat_grid, rules),
+            current: seat_grid.seats.clone(),
+            next: seat_grid.seats.clone(),
+            worklist: None,
+            rounds: 0,
         }
     }
 
-    fn apply_round_visible(&self, visibility_graph: &HashMap<usize, Vec<usize>>) -> Self {
-        let mut new_seats = Vec::new();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let index = (y * self.width + x) as usize;
-                let current = &self.seats[index];
-                if current.is_none() {
-                    new_seats.insert(index, current.to_owned());
-                    continue;
+    fn with_worklist(mut self) -> Self {
+        let cells = self.current.len();
+        self.worklist = Some(Worklist {
+            watchers: self.neighbors.reversed(),
+            pending: (0..cells).collect(),
+            queued: vec![true; cells],
+            changes: Vec::with_capacity(cells),
+        });
+        self
+    }
+
+    fn next_seat(&self, cell: usize) -> Option<Seat> {
+        self.current[cell].map(|seat| {
+            let occupied_neighbors =
+                count_occupied(&self.current, self.neighbors.of(cell).iter().copied());
+            self.rules.next_seat(seat, occupied_neighbors)
+        })
+    }
+
+    // Runs a round and returns how many seats changed
+    fn step(&mut self) -> usize {
+        self.rounds += 1;
+        let mut worklist = match self.worklist.take() {
+            Some(worklist) => worklist,
+            None => {
+                let mut changed = 0;
+                for cell in 0..self.current.len() {
+                    let next = self.next_seat(cell);
+                    if next != self.current[cell] {
+                        changed += 1;
+                    }
+                    self.next[cell] = next;
                 }
-                let visible_occupied = visibility_graph[&index]
-                    .iter()
-                    .filter(|&&visible| self.seats[visible] == Some(Seat::Occupied))
-                    .count();
-                if current == &Some(Seat::Empty) && visible_occupied == 0 {
-                    new_seats.insert(index, Some(Seat::Occupied));
-                } else if current == &Some(Seat::Occupied) && visible_occupied >= 5 {
-                    new_seats.insert(index, Some(Seat::Empty));
-                } else {
-                    new_seats.insert(index, current.to_owned());
+                std::mem::swap(&mut self.current, &mut self.next);
+                return changed;
+            }
+        };
+
+        worklist.changes.clear();
+        for &cell in &worklist.pending {
+            worklist.queued[cell] = false;
+            let next = self.next_seat(cell);
+            if next != self.current[cell] {
+                worklist.changes.push((cell, next.unwrap()));
+            }
+        }
+        worklist.pending.clear();
+        for &(cell, seat) in &worklist.changes {
+            self.current[cell] = Some(seat);
+            for &watcher in worklist.watchers.of(cell) {
+                if !worklist.queued[watcher] {
+                    worklist.queued[watcher] = true;
+                    worklist.pending.push(watcher);
                 }
             }
         }
+        let changed = worklist.changes.len();
+        self.worklist = Some(worklist);
+        changed
+    }
 
-        Self {
+    fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.current.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Runs until the grid stops changing or repeats an earlier state. States are remembered by
+    // hash alone, so a collision could report a cycle early, but with 64 bits that's not
+    // worth keeping whole grids around for.
+    fn run(&mut self, max_rounds: usize) -> Settled {
+        let mut seen: HashMap<u64, usize> = HashMap::new();
+        seen.insert(self.state_hash(), self.rounds);
+        while self.rounds < max_rounds {
+            if self.step() == 0 {
+                return Settled::Stable { round: self.rounds };
+            }
+            if let Some(previous) = seen.insert(self.state_hash(), self.rounds) {
+                return Settled::Oscillating {
+                    round: self.rounds,
+                    period: self.rounds - previous,
+                };
+            }
+        }
+        Settled::Unsettled {
+            rounds: self.rounds,
+        }
+    }
+
+    fn seat_grid(&self) -> SeatGrid {
+        SeatGrid {
             width: self.width,
             height: self.height,
-            seats: new_seats,
+            seats: self.current.clone(),
         }
     }
 }
 
-fn apply_rounds_until_stable_adjacent(seat_grid: SeatGrid) -> SeatGrid {
-    let mut next = seat_grid.apply_round_adjacent();
-    let mut prev = seat_grid;
-    while next != prev {
-        prev = next.clone();
-        next = prev.apply_round_adjacent();
+// Returns the grid once it's settled (or the guard ran out) and how it got there
+fn apply_rounds(seat_grid: SeatGrid, rules: &Rules, max_rounds: usize) -> (SeatGrid, Settled) {
+    let mut simulation = Simulation::new(&seat_grid, rules);
+    let settled = simulation.run(max_rounds);
+    (simulation.seat_grid(), settled)
+}
+
+fn apply_rounds_until_stable(seat_grid: SeatGrid, rules: &Rules) -> SeatGrid {
+    match apply_rounds(seat_grid, rules, MAX_ROUNDS) {
+        (stable, Settled::Stable { .. }) => stable,
+        (_, settled) => panic!("seating never stabilised: {:?}", settled),
     }
+}
 
-    next
+fn apply_rounds_until_stable_adjacent(seat_grid: SeatGrid) -> SeatGrid {
+    apply_rounds_until_stable(seat_grid, &Rules::adjacent())
 }
 
 fn apply_rounds_until_stable_visible(seat_grid: SeatGrid) -> SeatGrid {
-    let visibility_graph = seat_grid.get_visibility_graph();
-    let mut next = seat_grid.apply_round_visible(&visibility_graph);
-    let mut prev = seat_grid;
-    while next != prev {
-        prev = next.clone();
-        next = prev.apply_round_visible(&visibility_graph);
-    }
-
-    next
+    apply_rounds_until_stable(seat_grid, &Rules::visible())
 }
 
 pub fn p1() -> usize {
@@ -225,9 +478,62 @@ pub fn p2() -> usize {
     apply_rounds_until_stable_visible(SeatGrid::parse(INPUT)).total_occupied()
 }
 
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 11 simulate <adjacent|visible|offsets:dx,dy;...|sight:dx,dy;...> <occupy at most> <vacate at least> <edge|wrap> [layout] [max rounds]";
+    let (rules, path, max_rounds) = match args {
+        [command, neighborhood, occupy, vacate, boundary, rest @ ..]
+            if command == "simulate" && rest.len() <= 2 =>
+        {
+            let rules = neighborhood.parse().and_then(|neighborhood| {
+                Ok(Rules {
+                    neighborhood,
+                    occupy_at_most: occupy
+                        .parse()
+                        .map_err(|_| format!("invalid threshold '{}'", occupy))?,
+                    vacate_at_least: vacate
+                        .parse()
+                        .map_err(|_| format!("invalid threshold '{}'", vacate))?,
+                    boundary: boundary.parse()?,
+                })
+            });
+            let max_rounds = match rest.get(1).map_or(Ok(MAX_ROUNDS), |max| max.parse()) {
+                Ok(max_rounds) => max_rounds,
+                Err(_) => return usage.to_string(),
+            };
+            match rules {
+                Ok(rules) => (rules, rest.first(), max_rounds),
+                Err(err) => return format!("Error: {}", err),
+            }
+        }
+        _ => return usage.to_string(),
+    };
+
+    let input = match read_input(path, INPUT) {
+        Ok(input) => input,
+        Err(err) => return err,
+    };
+    let mut simulation = Simulation::new(&SeatGrid::parse(input.trim()), &rules).with_worklist();
+    let settled = match simulation.run(max_rounds) {
+        Settled::Stable { round } => format!("Stable after {} rounds", round),
+        Settled::Oscillating { round, period } => format!(
+            "Oscillating with period {}, first repeated after round {}",
+            period, round
+        ),
+        Settled::Unsettled { rounds } => format!("Still changing after {} rounds", rounds),
+    };
+    let seat_grid = simulation.seat_grid();
+    format!(
+        "{}\n\n{} with {} occupied",
+        seat_grid.to_string().trim_start(),
+        settled,
+        seat_grid.total_occupied()
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static SIMPLE: &str = r#"........
 .LLL.LLL
@@ -249,10 +555,12 @@ L.LLLLL.LL"#;
     #[test]
     fn p1_simple() {
         let grid = SeatGrid::parse(SIMPLE);
+        let count_occupied_adjacent =
+            |x, y| count_occupied(&grid.seats, grid.neighbors_of(x, y, &Rules::adjacent()));
 
-        assert_eq!(2, grid.count_occupied_adjacent(3, 1));
-        assert_eq!(6, grid.count_occupied_adjacent(1, 3));
-        assert_eq!(1, grid.count_occupied_adjacent(6, 4));
+        assert_eq!(2, count_occupied_adjacent(3, 1));
+        assert_eq!(6, count_occupied_adjacent(1, 3));
+        assert_eq!(1, count_occupied_adjacent(6, 4));
     }
 
     #[test]
@@ -282,4 +590,143 @@ L.LLLLL.LL"#;
 
         assert_eq!(2117, stable.total_occupied());
     }
+
+    #[test]
+    fn custom_rules() {
+        // the puzzle's rules written out as configuration
+        let visible = Rules {
+            neighborhood: "visible".parse().unwrap(),
+            occupy_at_most: 0,
+            vacate_at_least: 5,
+            boundary: Boundary::Edge,
+        };
+        let (stable, settled) = apply_rounds(SeatGrid::parse(EXAMPLE), &visible, MAX_ROUNDS);
+        assert_eq!(26, stable.total_occupied());
+        assert_eq!(Settled::Stable { round: 7 }, settled);
+
+        // looking left off a wrapped row sees the seat at the far end
+        let row = SeatGrid::parse("LLL");
+        let wrapped = Rules {
+            neighborhood: "sight:1,0;-1,0".parse().unwrap(),
+            occupy_at_most: 0,
+            vacate_at_least: 2,
+            boundary: Boundary::Wrap,
+        };
+        assert_eq!(vec![1, 2], row.neighbors_of(0, 0, &wrapped));
+        let edge = Rules {
+            boundary: Boundary::Edge,
+            ..wrapped
+        };
+        assert_eq!(vec![1], row.neighbors_of(0, 0, &edge));
+
+        let knight: Neighborhood = "offsets:1,2;2,1".parse().unwrap();
+        assert_eq!(Neighborhood::Offsets(vec![(1, 2), (2, 1)]), knight);
+        assert!("offsets:0,0".parse::<Neighborhood>().is_err());
+        assert!("diagonal".parse::<Neighborhood>().is_err());
+    }
+
+    #[test]
+    fn small_wrapped_grids() {
+        let wrapped = Rules {
+            boundary: Boundary::Wrap,
+            ..Rules::adjacent()
+        };
+        let square = SeatGrid::parse("LL\nLL");
+        assert_eq!(vec![3, 2, 1], square.neighbors_of(0, 0, &wrapped));
+        assert_eq!(
+            vec![2, 1],
+            SeatGrid::parse("LLL").neighbors_of(0, 0, &wrapped)
+        );
+        assert!(SeatGrid::parse("L").neighbors_of(0, 0, &wrapped).is_empty());
+
+        // three neighbors each, so nobody ever has the four it takes to leave
+        let (grid, settled) = apply_rounds(square, &wrapped, MAX_ROUNDS);
+        assert_eq!(4, grid.total_occupied());
+        assert_eq!(Settled::Stable { round: 2 }, settled);
+    }
+
+    #[test]
+    fn worklist_matches_full_simulation() {
+        for rules in &[Rules::adjacent(), Rules::visible()] {
+            let grid = SeatGrid::parse(INPUT);
+            let mut full = Simulation::new(&grid, rules);
+            let mut worklist = Simulation::new(&grid, rules).with_worklist();
+
+            loop {
+                let changed = full.step();
+                assert_eq!(changed, worklist.step());
+                assert_eq!(full.seat_grid(), worklist.seat_grid());
+                if changed == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_table() {
+        let grid = SeatGrid::parse(SIMPLE);
+        let rules = Rules {
+            neighborhood: "offsets:1,0".parse().unwrap(),
+            ..Rules::adjacent()
+        };
+        let table = NeighborTable::build(&grid, &rules);
+        let reversed = table.reversed();
+
+        assert_eq!(&[10], table.of(9));
+        assert_eq!(&[] as &[usize], table.of(0));
+        assert_eq!(&[9], reversed.of(10));
+        assert_eq!(&[] as &[usize], reversed.of(9));
+    }
+
+    #[test]
+    fn oscillation() {
+        // two seats that fill together and empty together
+        let blinker = Rules {
+            occupy_at_most: 0,
+            vacate_at_least: 1,
+            ..Rules::adjacent()
+        };
+        let (grid, settled) = apply_rounds(SeatGrid::parse("LL"), &blinker, MAX_ROUNDS);
+        assert_eq!(
+            Settled::Oscillating {
+                round: 2,
+                period: 2
+            },
+            settled
+        );
+        assert_eq!(SeatGrid::parse("LL"), grid);
+
+        let mut simulation = Simulation::new(&SeatGrid::parse(EXAMPLE), &blinker).with_worklist();
+        assert_eq!(
+            Settled::Oscillating {
+                round: 2,
+                period: 2
+            },
+            simulation.run(MAX_ROUNDS)
+        );
+
+        let (_, settled) = apply_rounds(SeatGrid::parse(EXAMPLE), &Rules::adjacent(), 3);
+        assert_eq!(Settled::Unsettled { rounds: 3 }, settled);
+    }
+
+    #[test]
+    fn tools_arguments() {
+        let simulate = |extra: &[&str]| {
+            let mut words = vec!["simulate", "adjacent", "0", "4", "edge"];
+            words.extend(extra);
+            tools(&args(&words))
+        };
+
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["simulate", "adjacent", "0", "4"])).starts_with("Usage:"));
+        assert!(simulate(&["no/such/file", "many"]).starts_with("Usage:"));
+        assert!(tools(&args(&["simulate", "diagonal", "0", "4", "edge"])).starts_with("Error: "));
+        assert_eq!(
+            "Error: invalid threshold 'x'",
+            tools(&args(&["simulate", "adjacent", "x", "4", "edge"]))
+        );
+        assert!(simulate(&[]).ends_with("Stable after 75 rounds with 2319 occupied"));
+        assert!(simulate(&["no/such/file"]).starts_with("no/such/file: "));
+    }
 }
@@ -1,116 +1,366 @@
+use crate::tools::read_input;
+use std::fmt;
+
 static INPUT: &'static str = include_str!("assets/day_12_input.txt");
 
-struct Ship {
-    x: f64,
-    y: f64,
-    way_x: f64,
-    way_y: f64,
-    dir: i64,
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NavError {
+    InvalidAction(String),
+    // a turn that isn't a multiple of 90 degrees, without arbitrary angles allowed
+    UnsupportedAngle(i64),
+}
+
+impl fmt::Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavError::InvalidAction(action) => write!(f, "invalid action '{}'", action),
+            NavError::UnsupportedAngle(degrees) => {
+                write!(f, "can't turn {} degrees, only multiples of 90", degrees)
+            }
+        }
+    }
+}
+
+// In the order a left turn goes through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    East,
+    North,
+    West,
+    South,
 }
 
-fn to_rect(r: f64, theta: f64) -> (f64, f64) {
-    let x = r * theta.cos();
-    let y = r * theta.sin();
+impl Direction {
+    // Headings are degrees counterclockwise from east
+    fn from_degrees(degrees: i64) -> Option<Self> {
+        match degrees.rem_euclid(360) {
+            0 => Some(Direction::East),
+            90 => Some(Direction::North),
+            180 => Some(Direction::West),
+            270 => Some(Direction::South),
+            _ => None,
+        }
+    }
 
-    (x, y)
+    fn offset(self) -> (i64, i64) {
+        match self {
+            Direction::East => (1, 0),
+            Direction::North => (0, 1),
+            Direction::West => (-1, 0),
+            Direction::South => (0, -1),
+        }
+    }
 }
 
-fn rotate_xy(x: f64, y: f64, value: i32) -> (f64, f64) {
-    match value {
-        90 | -270 => (-y, x),
-        180 | -180 => (-x, -y),
-        270 | -90 => (y, -x),
-        _ => unreachable!(),
+// Exact rotation counterclockwise by a multiple of 90 degrees
+fn rotate_xy(x: i64, y: i64, degrees: i64) -> Result<(i64, i64), NavError> {
+    match degrees.rem_euclid(360) {
+        0 => Ok((x, y)),
+        90 => Ok((-y, x)),
+        180 => Ok((-x, -y)),
+        270 => Ok((y, -x)),
+        _ => Err(NavError::UnsupportedAngle(degrees)),
     }
 }
 
+// Rotation by any angle. Anything but a multiple of 90 degrees lands between grid points, so
+// each coordinate is rounded to the nearest integer (halves away from zero) straight away;
+// that error builds up with every rotation.
+fn rotate_xy_rounded(x: i64, y: i64, degrees: i64) -> (i64, i64) {
+    rotate_xy(x, y, degrees).unwrap_or_else(|_| {
+        let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+        let (x, y) = (x as f64, y as f64);
+        (
+            (x * cos - y * sin).round() as i64,
+            (x * sin + y * cos).round() as i64,
+        )
+    })
+}
+
+fn parse_action(nav_action: &str) -> Result<(char, i64), NavError> {
+    let invalid = || NavError::InvalidAction(nav_action.to_string());
+    let mut chars = nav_action.chars();
+    let instruction = chars.next().ok_or_else(invalid)?;
+    let value = chars.as_str().parse().map_err(|_| invalid())?;
+    Ok((instruction, value))
+}
+
+// Where the ship and its waypoint were after `step` actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TracePoint {
+    step: usize,
+    x: i64,
+    y: i64,
+    way_x: i64,
+    way_y: i64,
+    heading: i64,
+}
+
+// One row per point, with each trace's label in the first column
+fn traces_to_csv(traces: &[(&str, &[TracePoint])]) -> String {
+    let mut csv = "route,step,x,y,waypoint_x,waypoint_y,heading\n".to_string();
+    for (label, trace) in traces {
+        for point in trace.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                label, point.step, point.x, point.y, point.way_x, point.way_y, point.heading
+            ));
+        }
+    }
+    csv
+}
+
+static TRACE_COLORS: [&str; 4] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd"];
+
+// A standalone SVG drawing each trace as a polyline, with north up
+fn traces_to_svg(traces: &[(&str, &[TracePoint])]) -> String {
+    let points = traces.iter().flat_map(|(_, trace)| trace.iter());
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0, 0, 0, 0);
+    for point in points {
+        min_x = min_x.min(point.x);
+        max_x = max_x.max(point.x);
+        min_y = min_y.min(point.y);
+        max_y = max_y.max(point.y);
+    }
+    let margin = ((max_x - min_x).max(max_y - min_y) / 20).max(1);
+
+    // SVG's y axis points down, so flip every y
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+        min_x - margin,
+        -max_y - margin,
+        max_x - min_x + 2 * margin,
+        max_y - min_y + 2 * margin
+    );
+    for (index, (label, trace)) in traces.iter().enumerate() {
+        let points: Vec<String> = trace
+            .iter()
+            .map(|point| format!("{},{}", point.x, -point.y))
+            .collect();
+        svg.push_str(&format!(
+            "  <polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\" points=\"{}\">\n    <title>{}</title>\n  </polyline>\n",
+            TRACE_COLORS[index % TRACE_COLORS.len()],
+            points.join(" "),
+            label
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+struct Ship {
+    x: i64,
+    y: i64,
+    way_x: i64,
+    way_y: i64,
+    // degrees counterclockwise from east
+    heading: i64,
+    arbitrary_angles: bool,
+    // every position so far, when recording
+    trace: Option<Vec<TracePoint>>,
+}
+
 impl Ship {
     fn new() -> Self {
         Self {
-            x: 0.0,
-            y: 0.0,
-            way_x: 10.0,
-            way_y: 1.0,
-            dir: 0,
+            x: 0,
+            y: 0,
+            way_x: 10,
+            way_y: 1,
+            heading: 0,
+            arbitrary_angles: false,
+            trace: None,
+        }
+    }
+
+    fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self.record();
+        self
+    }
+
+    fn record(&mut self) {
+        let point = TracePoint {
+            step: self.trace.as_ref().map_or(0, Vec::len),
+            x: self.x,
+            y: self.y,
+            way_x: self.way_x,
+            way_y: self.way_y,
+            heading: self.heading,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.push(point);
+        }
+    }
+
+    // Empty unless the ship was built `with_trace`
+    fn trace(&self) -> impl Iterator<Item = &TracePoint> {
+        self.trace.iter().flatten()
+    }
+
+    // Allows turns that aren't multiples of 90 degrees, rounding positions to the grid as
+    // described on `rotate_xy_rounded`
+    fn with_arbitrary_angles(mut self) -> Self {
+        self.arbitrary_angles = true;
+        self
+    }
+
+    fn rotate(&self, x: i64, y: i64, degrees: i64) -> Result<(i64, i64), NavError> {
+        if self.arbitrary_angles {
+            Ok(rotate_xy_rounded(x, y, degrees))
+        } else {
+            rotate_xy(x, y, degrees)
         }
     }
 
-    fn navigate(&mut self, nav_action: &str) {
-        let instruction = &nav_action[..1];
-        let value: i64 = nav_action[1..].parse().unwrap();
+    fn turn(&mut self, degrees: i64) -> Result<(), NavError> {
+        if !self.arbitrary_angles && degrees % 90 != 0 {
+            return Err(NavError::UnsupportedAngle(degrees));
+        }
+        self.heading = (self.heading + degrees).rem_euclid(360);
+        Ok(())
+    }
+
+    fn navigate(&mut self, nav_action: &str) -> Result<(), NavError> {
+        let (instruction, value) = parse_action(nav_action)?;
         match instruction {
-            "N" => self.y += value as f64,
-            "S" => self.y -= value as f64,
-            "E" => self.x += value as f64,
-            "W" => self.x -= value as f64,
-            "L" => self.dir += value,
-            "R" => self.dir -= value,
-            "F" => {
-                let theta = (self.dir as f64).to_radians();
-                let (x, y) = to_rect(value as f64, theta);
+            'N' => self.y += value,
+            'S' => self.y -= value,
+            'E' => self.x += value,
+            'W' => self.x -= value,
+            'L' => self.turn(value)?,
+            'R' => self.turn(-value)?,
+            'F' => {
+                let (x, y) = match Direction::from_degrees(self.heading) {
+                    Some(dir) => {
+                        let (x, y) = dir.offset();
+                        (x * value, y * value)
+                    }
+                    // only reachable with arbitrary angles, which round the move to the grid
+                    None => self.rotate(value, 0, self.heading)?,
+                };
                 self.x += x;
                 self.y += y;
             }
-            _ => panic!("unsupported instruction"),
+            _ => return Err(NavError::InvalidAction(nav_action.to_string())),
         }
+        Ok(())
     }
 
-    fn manhattan_dist_to_origin(&self) -> f64 {
+    fn manhattan_dist_to_origin(&self) -> i64 {
         self.x.abs() + self.y.abs()
     }
 
-    fn follow_path(&mut self, instructions: &str) -> i32 {
+    fn follow_path(&mut self, instructions: &str) -> Result<i64, NavError> {
         for line in instructions.lines() {
-            self.navigate(line);
+            self.navigate(line)?;
+            self.record();
         }
-        self.manhattan_dist_to_origin() as i32
+        Ok(self.manhattan_dist_to_origin())
     }
 
-    fn navigate_waypoint(&mut self, nav_action: &str) {
-        let instruction = &nav_action[..1];
-        let value: i32 = nav_action[1..].parse().unwrap();
+    fn navigate_waypoint(&mut self, nav_action: &str) -> Result<(), NavError> {
+        let (instruction, value) = parse_action(nav_action)?;
         match instruction {
-            "N" => self.way_y += value as f64,
-            "S" => self.way_y -= value as f64,
-            "E" => self.way_x += value as f64,
-            "W" => self.way_x -= value as f64,
-            "L" => {
-                let (x, y) = rotate_xy(self.way_x, self.way_y, value);
+            'N' => self.way_y += value,
+            'S' => self.way_y -= value,
+            'E' => self.way_x += value,
+            'W' => self.way_x -= value,
+            'L' => {
+                let (x, y) = self.rotate(self.way_x, self.way_y, value)?;
                 self.way_x = x;
                 self.way_y = y;
             }
-            "R" => {
-                let (x, y) = rotate_xy(self.way_x, self.way_y, -value);
+            'R' => {
+                let (x, y) = self.rotate(self.way_x, self.way_y, -value)?;
                 self.way_x = x;
                 self.way_y = y;
             }
-            "F" => {
-                self.x += self.way_x * (value as f64);
-                self.y += self.way_y * (value as f64);
+            'F' => {
+                self.x += self.way_x * value;
+                self.y += self.way_y * value;
             }
-            _ => panic!("unsupported instruction"),
+            _ => return Err(NavError::InvalidAction(nav_action.to_string())),
         }
+        Ok(())
     }
 
-    fn follow_path_with_waypoint(&mut self, instructions: &str) -> i32 {
+    fn follow_path_with_waypoint(&mut self, instructions: &str) -> Result<i64, NavError> {
         for line in instructions.lines() {
-            self.navigate_waypoint(line);
+            self.navigate_waypoint(line)?;
+            self.record();
         }
-        self.manhattan_dist_to_origin() as i32
+        Ok(self.manhattan_dist_to_origin())
     }
 }
 
-pub fn p1() -> i32 {
-    Ship::new().follow_path(INPUT)
+pub fn p1() -> i64 {
+    Ship::new().follow_path(INPUT).expect("invalid route")
 }
 
-pub fn p2() -> i32 {
-    Ship::new().follow_path_with_waypoint(INPUT)
+pub fn p2() -> i64 {
+    Ship::new()
+        .follow_path_with_waypoint(INPUT)
+        .expect("invalid route")
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 12 <ship|waypoint> [right|any] [route]\n\tadvent2020 12 trace <csv|svg> [route]";
+    let result = match args {
+        [command, format, rest @ ..]
+            if command == "trace" && (format == "csv" || format == "svg") && rest.len() <= 1 =>
+        {
+            read_input(rest.first(), INPUT).and_then(|input| {
+                let mut ship = Ship::new().with_trace();
+                let mut waypoint = Ship::new().with_trace();
+                ship.follow_path(input.trim())
+                    .and(waypoint.follow_path_with_waypoint(input.trim()))
+                    .map_err(|err| err.to_string())?;
+                let ship_trace: Vec<TracePoint> = ship.trace().copied().collect();
+                let waypoint_trace: Vec<TracePoint> = waypoint.trace().copied().collect();
+                let traces = [("ship", &ship_trace[..]), ("waypoint", &waypoint_trace[..])];
+                Ok(if format == "csv" {
+                    traces_to_csv(&traces)
+                } else {
+                    traces_to_svg(&traces)
+                })
+            })
+        }
+        [mode, rest @ ..] if (mode == "ship" || mode == "waypoint") && rest.len() <= 2 => {
+            let (arbitrary_angles, path) = match rest {
+                [angles, path @ ..] if angles == "right" || angles == "any" => {
+                    (angles == "any", path.first())
+                }
+                [path] => (false, Some(path)),
+                [] => (false, None),
+                _ => return usage.to_string(),
+            };
+            read_input(path, INPUT).and_then(|input| {
+                let mut ship = Ship::new();
+                if arbitrary_angles {
+                    ship = ship.with_arbitrary_angles();
+                }
+                let distance = if mode == "ship" {
+                    ship.follow_path(input.trim())
+                } else {
+                    ship.follow_path_with_waypoint(input.trim())
+                };
+                distance
+                    .map(|distance| {
+                        format!("Position: ({}, {})\nDistance: {}", ship.x, ship.y, distance)
+                    })
+                    .map_err(|err| err.to_string())
+            })
+        }
+        _ => Ok(usage.to_string()),
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"F10
 N3
@@ -122,27 +372,111 @@ F11"#;
     fn p1_example() {
         let distance = Ship::new().follow_path(EXAMPLE);
 
-        assert_eq!(25, distance);
+        assert_eq!(Ok(25), distance);
     }
 
     #[test]
     fn p1_correct_answer() {
         let distance = Ship::new().follow_path(INPUT);
 
-        assert_eq!(420, distance);
+        assert_eq!(Ok(420), distance);
     }
 
     #[test]
     fn p2_example() {
         let distance = Ship::new().follow_path_with_waypoint(EXAMPLE);
 
-        assert_eq!(286, distance);
+        assert_eq!(Ok(286), distance);
     }
 
     #[test]
     fn p2_correct_answer() {
         let distance = Ship::new().follow_path_with_waypoint(INPUT);
 
-        assert_eq!(42073, distance);
+        assert_eq!(Ok(42073), distance);
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(Ok((-1, 10)), rotate_xy(10, 1, 90));
+        assert_eq!(Ok((1, -10)), rotate_xy(10, 1, -90));
+        assert_eq!(Ok((10, 1)), rotate_xy(10, 1, 720));
+        assert_eq!(Err(NavError::UnsupportedAngle(45)), rotate_xy(10, 1, 45));
+        // (10 cos 45, 10 sin 45) is about (7.07, 7.07)
+        assert_eq!((7, 7), rotate_xy_rounded(10, 0, 45));
+        assert_eq!((-1, 10), rotate_xy_rounded(10, 1, 90));
+    }
+
+    #[test]
+    fn arbitrary_angles() {
+        assert_eq!(
+            Err(NavError::UnsupportedAngle(-45)),
+            Ship::new().follow_path("R45\nF10")
+        );
+        assert_eq!(
+            Err(NavError::InvalidAction("X1".to_string())),
+            Ship::new().follow_path("X1")
+        );
+
+        let mut ship = Ship::new().with_arbitrary_angles();
+        assert_eq!(Ok(16), ship.follow_path("L45\nF10\nL45\nF2"));
+        assert_eq!((7, 9), (ship.x, ship.y));
+
+        let mut ship = Ship::new().with_arbitrary_angles();
+        assert_eq!(Ok(286), ship.follow_path_with_waypoint(EXAMPLE));
+
+        let mut ship = Ship::new().with_arbitrary_angles();
+        // the waypoint (10, 1) turns to about (8.16, 5.87), which rounds to (8, 6)
+        assert_eq!(Ok(140), ship.follow_path_with_waypoint("L30\nF10"));
+    }
+
+    #[test]
+    fn traces() {
+        let mut ship = Ship::new();
+        ship.follow_path(EXAMPLE).unwrap();
+        assert_eq!(0, ship.trace().count());
+
+        let mut ship = Ship::new().with_trace();
+        ship.follow_path(EXAMPLE).unwrap();
+        let positions: Vec<(i64, i64)> = ship.trace().map(|point| (point.x, point.y)).collect();
+        assert_eq!(
+            vec![(0, 0), (10, 0), (10, 3), (17, 3), (17, 3), (17, -8)],
+            positions
+        );
+
+        let mut waypoint = Ship::new().with_trace();
+        waypoint.follow_path_with_waypoint(EXAMPLE).unwrap();
+        let last = waypoint.trace().last().copied().unwrap();
+        assert_eq!(
+            (5, 214, -72, 4, -10),
+            (last.step, last.x, last.y, last.way_x, last.way_y)
+        );
+
+        let ship_trace: Vec<TracePoint> = ship.trace().copied().collect();
+        let csv = traces_to_csv(&[("ship", &ship_trace[..2])]);
+        assert_eq!(
+            "route,step,x,y,waypoint_x,waypoint_y,heading\nship,0,0,0,10,1,0\nship,1,10,0,10,1,0\n",
+            csv
+        );
+
+        let svg = traces_to_svg(&[("ship", &ship_trace)]);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -4 19 13\">")
+        );
+        assert!(svg.contains("points=\"0,0 10,0 10,-3 17,-3 17,-3 17,8\""));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["trace", "png"])).starts_with("Usage:"));
+        assert!(tools(&args(&["ship", "any", "route", "extra"])).starts_with("Usage:"));
+        assert!(tools(&args(&["ship"])).ends_with("Distance: 420"));
+        assert!(tools(&args(&["waypoint", "any"])).ends_with("Distance: 42073"));
+        assert!(tools(&args(&["trace", "csv"])).lines().count() > 1);
+        assert!(
+            tools(&args(&["ship", "right", "no/such/file"])).starts_with("Error: no/such/file: ")
+        );
     }
 }
@@ -1,85 +1,300 @@
-use std::collections::HashMap;
+use crate::tools::read_input;
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, str::FromStr};
 
 static INPUT: &'static str = include_str!("assets/day_13_input.txt");
 
-fn get_soonest_bus(start: i64, bus_ids: Vec<i64>) -> (i64, i64) {
-    let mut min_id = -1;
-    let mut min_time = -1;
-    for bus_id in bus_ids {
-        let next_bus_time = bus_id - (start % bus_id);
-        if min_id == -1 || next_bus_time < min_time {
-            min_id = bus_id;
-            min_time = next_bus_time;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schedule {
+    // the earliest you could leave
+    earliest: i64,
+    // the bus at each position of the timetable, or `None` for an `x`
+    buses: Vec<Option<i64>>,
+}
+
+fn parse_buses(line: &str) -> Result<Vec<Option<i64>>, String> {
+    line.trim()
+        .split(',')
+        .map(|token| match token {
+            "x" => Ok(None),
+            _ => match token.parse() {
+                Ok(id) if id > 0 => Ok(Some(id)),
+                _ => Err(format!("invalid bus '{}'", token)),
+            },
+        })
+        .collect()
+}
+
+// The earliest timestamp on one line, then the buses separated by commas
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let earliest = lines
+            .next()
+            .and_then(|line| line.trim().parse().ok())
+            .ok_or("expected a timestamp on the first line")?;
+        let buses = parse_buses(lines.next().ok_or("expected a line of buses")?)?;
+        Ok(Self { earliest, buses })
+    }
+}
+
+impl Schedule {
+    fn bus_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.buses.iter().flatten().copied()
+    }
+
+    // The next `count` departures at or after `time`, as (time, bus) in the order they leave
+    fn next_departures(&self, time: i64, count: usize) -> Vec<(i64, i64)> {
+        let mut upcoming: BinaryHeap<Reverse<(i64, i64)>> = self
+            .bus_ids()
+            .map(|bus| Reverse((time + (-time).rem_euclid(bus), bus)))
+            .collect();
+        let mut departures = Vec::new();
+        while departures.len() < count {
+            let Reverse((departure, bus)) = match upcoming.pop() {
+                Some(next) => next,
+                None => break,
+            };
+            departures.push((departure, bus));
+            upcoming.push(Reverse((departure + bus, bus)));
         }
+        departures
+    }
+
+    // When each (bus, offset) pair has the bus leaving `offset` minutes after some time t. The
+    // earliest such t is the remainder and the alignment repeats every modulus minutes.
+    fn alignment(&self, offsets: &[(i64, i64)]) -> Result<Congruence, String> {
+        let congruences = offsets
+            .iter()
+            .map(|&(bus, offset)| {
+                if !self.bus_ids().any(|id| id == bus) {
+                    return Err(format!("bus {} isn't on the schedule", bus));
+                }
+                Congruence::new(-offset as i128, bus as i128).map_err(|err| err.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        solve_crt(&congruences).map_err(|err| err.to_string())
     }
 
-    (min_id, min_time)
+    // Every bus leaving as many minutes after t as its position in the timetable
+    fn timetable_alignment(&self) -> Result<Congruence, String> {
+        let offsets: Vec<(i64, i64)> = self
+            .buses
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, bus)| bus.map(|bus| (bus, offset as i64)))
+            .collect();
+        self.alignment(&offsets)
+    }
 }
 
 fn get_mult_time(input: &str) -> i64 {
-    let mut input_lines = input.lines();
-    let start_time: i64 = input_lines.next().unwrap().parse().unwrap();
-    let bus_ids: Vec<i64> = input_lines
-        .next()
-        .unwrap()
-        .split(',')
-        .filter_map(|token| token.parse::<i64>().ok())
-        .collect();
+    let schedule: Schedule = input.parse().unwrap();
+    let (time, id) = schedule.next_departures(schedule.earliest, 1)[0];
+    id * (time - schedule.earliest)
+}
 
-    let (id, time) = get_soonest_bus(start_time, bus_ids);
-    id * time
+// x ≡ remainder (mod modulus)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Congruence {
+    remainder: i128,
+    modulus: i128,
 }
 
-fn naive_mmi(target: i64, modulo: i64) -> i64 {
-    let mut i = 1;
-    loop {
-        if (target * i) % modulo == 1 {
-            break i;
+impl Congruence {
+    fn new(remainder: i128, modulus: i128) -> Result<Self, CrtError> {
+        if modulus <= 0 {
+            return Err(CrtError::InvalidModulus(modulus));
         }
-        i += 1;
+        Ok(Self {
+            remainder: remainder.rem_euclid(modulus),
+            modulus,
+        })
     }
 }
 
-fn calculate_crt(value_mod_pairs: &HashMap<i64, i64>) -> i64 {
-    let prod: i64 = value_mod_pairs.keys().product();
-    let mut result = 0;
-    for (&id, &modulo) in value_mod_pairs.iter() {
-        let y = prod / id;
-        let z = naive_mmi(y, id);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrtError {
+    InvalidModulus(i128),
+    // this congruence contradicts the ones before it
+    Inconsistent(Congruence),
+    // the combined modulus doesn't fit in 128 bits
+    Overflow,
+}
+
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrtError::InvalidModulus(modulus) => write!(f, "invalid modulus {}", modulus),
+            CrtError::Inconsistent(congruence) => write!(
+                f,
+                "x = {} (mod {}) contradicts the other congruences",
+                congruence.remainder, congruence.modulus
+            ),
+            CrtError::Overflow => write!(f, "combined modulus overflows 128 bits"),
+        }
+    }
+}
 
-        result += modulo * y * z;
+// Returns (gcd, x, y) where a * x + b * y = gcd
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_x, x) = (x, old_x - quotient * x);
+        (old_y, y) = (y, old_y - quotient * y);
     }
+    (old_r, old_x, old_y)
+}
 
-    result % prod
+fn mod_inverse(value: i128, modulus: i128) -> Option<i128> {
+    match extended_gcd(value.rem_euclid(modulus), modulus) {
+        (1, x, _) => Some(x.rem_euclid(modulus)),
+        _ => None,
+    }
 }
 
-fn get_earliest_departure_sequence(bus_ids_input: &str) -> i64 {
-    let bus_ids: HashMap<usize, i64> = bus_ids_input
-        .split(',')
-        .enumerate()
-        .filter_map(|(i, id)| id.parse::<i64>().ok().map(|id| (i, id)))
-        .collect();
+// (a * b) mod modulus for non-negative a and b below it, falling back to doubling when the
+// product doesn't fit
+fn mul_mod(a: i128, b: i128, modulus: i128) -> i128 {
+    if let Some(product) = a.checked_mul(b) {
+        return product % modulus;
+    }
+    // x + y mod modulus, without overflowing even when the modulus is close to the limit
+    let add_mod = |x: i128, y: i128| {
+        if x >= modulus - y {
+            x - (modulus - y)
+        } else {
+            x + y
+        }
+    };
+    let (mut a, mut b, mut result) = (a, b, 0);
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_mod(result, a);
+        }
+        a = add_mod(a, a);
+        b >>= 1;
+    }
+    result
+}
+
+// Merges two congruences into one over the lcm of their moduli, which don't need to be coprime
+fn combine(first: Congruence, second: Congruence) -> Result<Congruence, CrtError> {
+    let (gcd, _, _) = extended_gcd(first.modulus, second.modulus);
+    let difference = second.remainder - first.remainder;
+    if difference % gcd != 0 {
+        return Err(CrtError::Inconsistent(second));
+    }
 
-    let with_modulos: HashMap<i64, i64> = bus_ids
+    // first.remainder + first.modulus * t works when t = difference / gcd * inverse, working
+    // mod second.modulus / gcd
+    let reduced = second.modulus / gcd;
+    let inverse = mod_inverse(first.modulus / gcd, reduced).unwrap();
+    let t = mul_mod((difference / gcd).rem_euclid(reduced), inverse, reduced);
+    let modulus = (first.modulus / gcd)
+        .checked_mul(second.modulus)
+        .ok_or(CrtError::Overflow)?;
+    let remainder = (first.remainder + mul_mod(first.modulus, t, modulus)) % modulus;
+    Ok(Congruence { remainder, modulus })
+}
+
+// The single congruence equivalent to all of them: its remainder is the smallest
+// non-negative solution and its modulus says how often solutions repeat
+fn solve_crt(congruences: &[Congruence]) -> Result<Congruence, CrtError> {
+    congruences
         .iter()
-        .map(|(index, id)| (*id, id - (*index as i64)))
-        .collect();
+        .try_fold(Congruence::new(0, 1)?, |solution, &congruence| {
+            combine(solution, congruence)
+        })
+}
 
-    calculate_crt(&with_modulos)
+fn get_earliest_departure_sequence(bus_ids_input: &str) -> Result<i128, String> {
+    let schedule = Schedule {
+        earliest: 0,
+        buses: parse_buses(bus_ids_input)?,
+    };
+    schedule
+        .timetable_alignment()
+        .map(|alignment| alignment.remainder)
 }
 
 pub fn p1() -> i64 {
     get_mult_time(INPUT)
 }
 
-pub fn p2() -> i64 {
+pub fn p2() -> i128 {
     let bus_ids_input = INPUT.lines().skip(1).next().unwrap();
-    get_earliest_departure_sequence(bus_ids_input)
+    get_earliest_departure_sequence(bus_ids_input).expect("no departure sequence")
+}
+
+fn parse_offsets(offsets: &str) -> Result<Vec<(i64, i64)>, String> {
+    offsets
+        .split(',')
+        .map(|pair| match pair.split_once(':') {
+            Some((bus, offset)) => match (bus.parse(), offset.parse()) {
+                (Ok(bus), Ok(offset)) => Ok((bus, offset)),
+                _ => Err(format!("invalid bus offset '{}'", pair)),
+            },
+            None => Err(format!("expected bus:offset, got '{}'", pair)),
+        })
+        .collect()
+}
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 13 next <time> <count> [schedule]\n\tadvent2020 13 align <bus:offset,...> [schedule]";
+    let (command, rest) = match args {
+        [command, rest @ ..] if command == "next" && (2..=3).contains(&rest.len()) => {
+            (command, rest)
+        }
+        [command, rest @ ..] if command == "align" && (1..=2).contains(&rest.len()) => {
+            (command, rest)
+        }
+        _ => return usage.to_string(),
+    };
+    let path = if command == "next" {
+        rest.get(2)
+    } else {
+        rest.get(1)
+    };
+    let input = match read_input(path, INPUT) {
+        Ok(input) => input,
+        Err(err) => return err,
+    };
+    let schedule: Schedule = match input.parse() {
+        Ok(schedule) => schedule,
+        Err(err) => return format!("Error: {}", err),
+    };
+
+    if command == "next" {
+        let (time, count) = match (rest[0].parse(), rest[1].parse()) {
+            (Ok(time), Ok(count)) => (time, count),
+            _ => return usage.to_string(),
+        };
+        return schedule
+            .next_departures(time, count)
+            .iter()
+            .map(|(departure, bus)| format!("{}: bus {}", departure, bus))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    match parse_offsets(&rest[0]).and_then(|offsets| schedule.alignment(&offsets)) {
+        Ok(alignment) => format!(
+            "Earliest: {}\nRepeats every: {}",
+            alignment.remainder, alignment.modulus
+        ),
+        Err(err) => format!("Error: {}", err),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"939
 7,13,x,x,59,x,31,19"#;
@@ -96,26 +311,106 @@ mod test {
 
     #[test]
     fn p2_crt_math() {
-        let earliest = calculate_crt(&hashmap! {
-            3 => 2,
-            4 => 3,
-            5 => 1
-        });
+        let congruence = |remainder, modulus| Congruence::new(remainder, modulus).unwrap();
+        let earliest = solve_crt(&[congruence(2, 3), congruence(3, 4), congruence(1, 5)]);
 
-        assert_eq!(11, earliest);
+        assert_eq!(Ok(congruence(11, 60)), earliest);
     }
 
     #[test]
     fn p2_example() {
         let earliest = get_earliest_departure_sequence("7,13,x,x,59,x,31,19");
 
-        assert_eq!(1068781, earliest);
+        assert_eq!(Ok(1068781), earliest);
     }
 
     #[test]
     fn p2_correct_answer() {
         let earliest = get_earliest_departure_sequence(INPUT.lines().skip(1).next().unwrap());
 
-        assert_eq!(842186186521918, earliest)
+        assert_eq!(Ok(842186186521918), earliest)
+    }
+
+    #[test]
+    fn crt_non_coprime_moduli() {
+        let congruence = |remainder, modulus| Congruence::new(remainder, modulus).unwrap();
+
+        assert_eq!(
+            Ok(congruence(10, 12)),
+            solve_crt(&[congruence(2, 4), congruence(4, 6)])
+        );
+        assert_eq!(
+            Err(CrtError::Inconsistent(congruence(2, 6))),
+            solve_crt(&[congruence(1, 4), congruence(2, 6)])
+        );
+        assert_eq!(Ok(congruence(0, 1)), solve_crt(&[]));
+        assert_eq!(Err(CrtError::InvalidModulus(0)), Congruence::new(1, 0));
+        assert_eq!(Ok(congruence(3, 4)), Congruence::new(-1, 4));
+    }
+
+    #[test]
+    fn crt_large_moduli() {
+        let first = Congruence::new(5, (1 << 61) - 1).unwrap();
+        let second = Congruence::new(7, 1_000_000_000_000_000_003).unwrap();
+        let solution = solve_crt(&[first, second]).unwrap();
+
+        assert_eq!(first.modulus * second.modulus, solution.modulus);
+        assert_eq!(5, solution.remainder % first.modulus);
+        assert_eq!(7, solution.remainder % second.modulus);
+
+        let huge = Congruence::new(0, i128::MAX / 2).unwrap();
+        assert_eq!(
+            Err(CrtError::Overflow),
+            solve_crt(&[huge, Congruence::new(0, 5).unwrap()])
+        );
+        assert_eq!((2, -1, 1), extended_gcd(4, 6));
+        assert_eq!(1, mul_mod(i128::MAX - 1, i128::MAX - 1, i128::MAX));
+        assert_eq!(None, mod_inverse(2, 4));
+    }
+
+    #[test]
+    fn schedule_queries() {
+        let schedule: Schedule = EXAMPLE.parse().unwrap();
+        assert_eq!(939, schedule.earliest);
+        assert_eq!(
+            vec![7, 13, 59, 31, 19],
+            schedule.bus_ids().collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            vec![(944, 59), (945, 7), (949, 13), (950, 19), (952, 7)],
+            schedule.next_departures(939, 5)
+        );
+        // a bus leaving right at the given time counts
+        assert_eq!(vec![(0, 7), (0, 13)], schedule.next_departures(0, 2));
+        // with no buses there's nothing to wait for, however many are asked for
+        let no_buses: Schedule = "939\nx,x".parse().unwrap();
+        assert_eq!(
+            Vec::<(i64, i64)>::new(),
+            no_buses.next_departures(939, usize::MAX)
+        );
+
+        // from the puzzle: 17,x,13,19 first lines up at 3417
+        let schedule: Schedule = "0\n17,x,13,19".parse().unwrap();
+        assert_eq!(
+            Ok(Congruence::new(3417, 17 * 13 * 19).unwrap()),
+            schedule.timetable_alignment()
+        );
+        let pair = schedule.alignment(&[(17, 0), (13, 2)]).unwrap();
+        assert_eq!((102, 221), (pair.remainder, pair.modulus));
+        assert!(schedule.alignment(&[(7, 0)]).is_err());
+
+        assert!("939\n7,y".parse::<Schedule>().is_err());
+        assert!("7,13".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["next", "939"])).starts_with("Usage:"));
+        assert!(tools(&args(&["next", "soon", "1"])).starts_with("Usage:"));
+        assert_eq!(1, tools(&args(&["next", "939", "1"])).lines().count());
+        assert!(tools(&args(&["align", "7:0,7:1"])).starts_with("Error: "));
+        assert!(tools(&args(&["next", "939", "1", "no/such/file"])).starts_with("no/such/file: "));
     }
 }
@@ -1,3 +1,4 @@
+use crate::tools::read_input;
 use std::collections::HashMap;
 
 static INPUT: &'static str = include_str!("assets/day_14_input.txt");
@@ -33,15 +34,6 @@ impl BitMask {
         }
     }
 
-    fn set_value(&mut self, power: u32, inv: bool) {
-        let to_add = 1 << power;
-        if inv {
-            self.inv_mask += to_add;
-        } else {
-            self.mask += to_add;
-        }
-    }
-
     fn apply(&self, value: u64) -> u64 {
         let mut result = value;
         result |= self.mask;
@@ -51,50 +43,155 @@ impl BitMask {
     }
 }
 
-#[derive(Debug)]
+// A set of addresses: bits set in `floating` take every value, the rest are as in `fixed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AddressPattern {
+    fixed: u64,
+    floating: u64,
+}
+
+impl AddressPattern {
+    fn new(fixed: u64, floating: u64) -> Self {
+        Self {
+            fixed: fixed & !floating,
+            floating,
+        }
+    }
+
+    fn len(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    fn contains(&self, address: u64) -> bool {
+        address & !self.floating == self.fixed
+    }
+
+    // Every address in the pattern, in ascending order
+    fn addresses(&self) -> impl Iterator<Item = u64> {
+        let (fixed, floating) = (self.fixed, self.floating);
+        let mut next = Some(0u64);
+        std::iter::from_fn(move || {
+            let subset = next?;
+            // step to the next subset of the floating bits, stopping after wrapping round
+            let following = subset.wrapping_sub(floating) & floating;
+            next = if following == 0 {
+                None
+            } else {
+                Some(following)
+            };
+            Some(fixed | subset)
+        })
+    }
+
+    fn intersection(&self, other: &Self) -> Option<Self> {
+        let both_fixed = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & both_fixed != 0 {
+            return None;
+        }
+        Some(Self::new(
+            self.fixed | other.fixed,
+            self.floating & other.floating,
+        ))
+    }
+
+    // The addresses in this pattern but not `other`, as disjoint patterns. Each floating bit
+    // that the overlap pins down splits off the half that disagrees with it.
+    fn subtract(&self, other: &Self) -> Vec<Self> {
+        let overlap = match self.intersection(other) {
+            Some(overlap) => overlap,
+            None => return vec![*self],
+        };
+        let mut pieces = Vec::new();
+        let mut remaining = *self;
+        let mut split_bits = self.floating & !overlap.floating;
+        while split_bits != 0 {
+            let bit = split_bits & split_bits.wrapping_neg();
+            split_bits &= !bit;
+            remaining.floating &= !bit;
+            pieces.push(Self::new(
+                (remaining.fixed & !bit) | (!overlap.fixed & bit),
+                remaining.floating,
+            ));
+            remaining.fixed = (remaining.fixed & !bit) | (overlap.fixed & bit);
+        }
+        pieces
+    }
+}
+
+#[derive(Debug, Clone)]
 struct BitMaskV2 {
-    masks: Vec<BitMask>,
+    ones: u64,
+    floating: u64,
 }
 
 impl From<&str> for BitMaskV2 {
     fn from(mask_str: &str) -> BitMaskV2 {
-        let mut masks = Vec::new();
-        masks.push(BitMask::new());
+        let mut mask = BitMaskV2::new();
         for (i, mask_char) in mask_str.chars().rev().enumerate() {
             match mask_char {
                 '0' => {}
-                '1' => {
-                    masks
-                        .iter_mut()
-                        .for_each(|mask| mask.set_value(i as u32, false));
-                }
-                'X' => {
-                    let mut new_masks = masks.clone();
-                    // map 0s mask entries onto existing masks
-                    masks
-                        .iter_mut()
-                        .for_each(|mask| mask.set_value(i as u32, true));
-                    // map 1s mask entries onto new masks
-                    new_masks
-                        .iter_mut()
-                        .for_each(|mask| mask.set_value(i as u32, false));
-                    masks.extend(new_masks);
-                }
+                '1' => mask.ones |= 1 << i,
+                'X' => mask.floating |= 1 << i,
                 _ => panic!("unexpected mask character"),
             }
         }
 
-        BitMaskV2 { masks }
+        mask
     }
 }
 
 impl BitMaskV2 {
     fn new() -> Self {
-        Self { masks: Vec::new() }
+        Self {
+            ones: 0,
+            floating: 0,
+        }
+    }
+
+    fn pattern(&self, address: u64) -> AddressPattern {
+        AddressPattern::new(address | self.ones, self.floating)
+    }
+}
+
+// Memory written through floating masks, kept as disjoint address patterns with one value each
+// so it never has to list individual addresses
+#[derive(Debug, Default)]
+struct FloatingMemory {
+    regions: Vec<(AddressPattern, u64)>,
+}
+
+impl FloatingMemory {
+    // Later writes win, so whatever this overlaps is cut out of the earlier regions
+    fn write(&mut self, pattern: AddressPattern, value: u64) {
+        let mut regions = Vec::with_capacity(self.regions.len() + 1);
+        for (region, region_value) in self.regions.drain(..) {
+            regions.extend(
+                region
+                    .subtract(&pattern)
+                    .into_iter()
+                    .map(|piece| (piece, region_value)),
+            );
+        }
+        regions.push((pattern, value));
+        self.regions = regions;
+    }
+
+    fn read(&self, address: u64) -> u64 {
+        self.regions
+            .iter()
+            .find(|(region, _)| region.contains(address))
+            .map_or(0, |&(_, value)| value)
+    }
+
+    fn len(&self) -> u128 {
+        self.regions.iter().map(|(region, _)| region.len()).sum()
     }
 
-    fn apply(&self, value: u64) -> Vec<u64> {
-        self.masks.iter().map(|mask| mask.apply(value)).collect()
+    fn sum(&self) -> u128 {
+        self.regions
+            .iter()
+            .map(|(region, value)| region.len() * *value as u128)
+            .sum()
     }
 }
 
@@ -135,14 +232,14 @@ impl InitProgram {
 
 struct InitProgramV2 {
     mask: BitMaskV2,
-    memory: HashMap<u64, u64>,
+    memory: FloatingMemory,
 }
 
 impl InitProgramV2 {
     fn new() -> Self {
         Self {
             mask: BitMaskV2::new(),
-            memory: HashMap::new(),
+            memory: FloatingMemory::default(),
         }
     }
 
@@ -151,29 +248,20 @@ impl InitProgramV2 {
             let mut instruction_tokens = instruction.split_whitespace();
             let left = instruction_tokens.next().unwrap();
             let right = instruction_tokens.last().unwrap();
-            // panic!(format!("instruction {}", instruction));
             match (left, right) {
                 ("mask", value) => self.mask = BitMaskV2::from(value),
                 (mem, value_str) => {
                     let (start, end) = (mem.find('[').unwrap(), mem.find(']').unwrap());
                     let base_addr: u64 = mem[start + 1..end].parse().unwrap();
                     let value: u64 = value_str.parse().unwrap();
-                    println!(
-                        "base_addr {}, value: {}\n\tmodifying {} addresses...",
-                        base_addr,
-                        value,
-                        self.mask.masks.len()
-                    );
-                    self.mask.apply(base_addr).iter().for_each(|addr| {
-                        self.memory.insert(*addr, value);
-                    });
+                    self.memory.write(self.mask.pattern(base_addr), value);
                 }
             }
         }
     }
 
-    fn sum_of_values(&self) -> u64 {
-        self.memory.values().sum()
+    fn sum_of_values(&self) -> u128 {
+        self.memory.sum()
     }
 }
 
@@ -183,7 +271,7 @@ fn run_init_program(input: &str) -> u64 {
     program.sum_of_values()
 }
 
-fn run_init_program_v2(input: &str) -> u64 {
+fn run_init_program_v2(input: &str) -> u128 {
     let mut program = InitProgramV2::new();
     program.run_instructions(input);
     program.sum_of_values()
@@ -193,13 +281,76 @@ pub fn p1() -> u64 {
     run_init_program(INPUT)
 }
 
-pub fn p2() -> u64 {
+pub fn p2() -> u128 {
     run_init_program_v2(INPUT)
 }
 
+// Addresses listed by `decode` before it just reports the rest
+const MAX_LISTED_ADDRESSES: usize = 16;
+
+pub fn tools(args: &[String]) -> String {
+    let usage = "Usage:\n\tadvent2020 14 decode <mask> <address>\n\tadvent2020 14 memory [program]\n\tadvent2020 14 read <address> [program]";
+    match args {
+        [command, mask, address] if command == "decode" => {
+            let address: u64 = match address.parse() {
+                Ok(address) => address,
+                Err(err) => return format!("Error: {}", err),
+            };
+            if mask.len() != 36 || !mask.chars().all(|c| "01X".contains(c)) {
+                return format!("Error: invalid mask {}", mask);
+            }
+            let pattern = BitMaskV2::from(mask.as_str()).pattern(address);
+            let mut lines: Vec<String> = pattern
+                .addresses()
+                .take(MAX_LISTED_ADDRESSES)
+                .map(|address| address.to_string())
+                .collect();
+            if pattern.len() > MAX_LISTED_ADDRESSES as u128 {
+                lines.push(format!(
+                    "... {} more",
+                    pattern.len() - MAX_LISTED_ADDRESSES as u128
+                ));
+            }
+            lines.join("\n")
+        }
+        [command, rest @ ..]
+            if (command == "memory" && rest.len() <= 1)
+                || (command == "read" && (1..=2).contains(&rest.len())) =>
+        {
+            let path = if command == "memory" {
+                rest.first()
+            } else {
+                rest.get(1)
+            };
+            let input = match read_input(path, INPUT) {
+                Ok(input) => input,
+                Err(err) => return err,
+            };
+            let mut program = InitProgramV2::new();
+            program.run_instructions(&input);
+
+            if command == "memory" {
+                format!(
+                    "addresses written: {}\nregions: {}\nsum: {}",
+                    program.memory.len(),
+                    program.memory.regions.len(),
+                    program.sum_of_values()
+                )
+            } else {
+                match rest[0].parse() {
+                    Ok(address) => program.memory.read(address).to_string(),
+                    Err(err) => format!("Error: {}", err),
+                }
+            }
+        }
+        _ => usage.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::tools::args;
 
     static EXAMPLE: &str = r#"mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
 mem[8] = 11
@@ -233,13 +384,18 @@ mem[26] = 1"#;
     fn p2_parsing_and_masking() {
         let bit_mask = BitMaskV2::from("000000000000000000000000000000X1001X");
 
-        assert_eq!(4, bit_mask.masks.len());
-        assert_eq!(vec![26, 27, 58, 59], bit_mask.apply(42));
+        let addresses = |pattern: AddressPattern| pattern.addresses().collect::<Vec<_>>();
+
+        assert_eq!(4, bit_mask.pattern(42).len());
+        assert_eq!(vec![26, 27, 58, 59], addresses(bit_mask.pattern(42)));
 
         let bit_mask = BitMaskV2::from("00000000000000000000000000000000X0XX");
 
-        assert_eq!(8, bit_mask.masks.len());
-        assert_eq!(vec![16, 17, 18, 19, 24, 25, 26, 27], bit_mask.apply(26));
+        assert_eq!(8, bit_mask.pattern(26).len());
+        assert_eq!(
+            vec![16, 17, 18, 19, 24, 25, 26, 27],
+            addresses(bit_mask.pattern(26))
+        );
     }
 
     #[test]
@@ -256,4 +412,55 @@ mem[26] = 1"#;
     fn p2_correct_answer() {
         assert_eq!(5272149590143, run_init_program_v2(INPUT));
     }
+
+    #[test]
+    fn pattern_subtraction() {
+        let pattern = |mask: &str| BitMaskV2::from(mask).pattern(0);
+        let everything = pattern("XXXX");
+        let pieces = everything.subtract(&pattern("1X0X"));
+
+        assert_eq!(12, pieces.iter().map(AddressPattern::len).sum::<u128>());
+        let mut remaining: Vec<u64> = pieces.iter().flat_map(|piece| piece.addresses()).collect();
+        remaining.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7, 10, 11, 14, 15], remaining);
+
+        assert_eq!(None, pattern("1X").intersection(&pattern("0X")));
+        assert_eq!(vec![pattern("1X")], pattern("1X").subtract(&pattern("0X")));
+        assert!(pattern("X1").subtract(&pattern("XX")).is_empty());
+    }
+
+    #[test]
+    fn many_floating_bits() {
+        let mut program = InitProgramV2::new();
+        program.run_instructions(
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\nmem[0] = 5\nmask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX0\nmem[0] = 1\nmask = 000000000000000000000000000000000000\nmem[7] = 9",
+        );
+
+        assert_eq!(1 << 36, program.memory.len());
+        assert_eq!(5 * (1 << 35) + (1 << 35) - 5 + 9, program.sum_of_values());
+        assert_eq!(1, program.memory.read(6));
+        assert_eq!(9, program.memory.read(7));
+        assert_eq!(5, program.memory.read(1 << 35 | 1));
+    }
+
+    #[test]
+    fn tools_arguments() {
+        assert!(tools(&args(&[])).starts_with("Usage:"));
+        assert!(tools(&args(&["memory", "a", "b"])).starts_with("Usage:"));
+        assert_eq!(
+            "Error: invalid mask 10X",
+            tools(&args(&["decode", "10X", "42"]))
+        );
+        assert_eq!(
+            "26\n27\n58\n59",
+            tools(&args(&[
+                "decode",
+                "000000000000000000000000000000X1001X",
+                "42"
+            ]))
+        );
+        assert!(tools(&args(&["memory"])).ends_with("sum: 5272149590143"));
+        assert!(tools(&args(&["read", "here"])).starts_with("Error: "));
+        assert!(tools(&args(&["read", "5", "no/such/file"])).starts_with("no/such/file: "));
+    }
 }
@@ -1,3 +1,4 @@
+#[cfg(test)]
 #[macro_use]
 extern crate maplit;
 #[macro_use]
@@ -30,10 +31,33 @@ mod day_22;
 mod day_23;
 mod day_24;
 mod day_25;
+mod tools;
 
 fn main() {
     let args: Vec<String> = args().collect();
-    let day: u32 = args[1].parse().expect("Usage:\n\tadvent2020 <day>");
+    let day: u32 = args[1]
+        .parse()
+        .expect("Usage:\n\tadvent2020 <day> [command [args...]]");
+
+    if args.len() > 2 {
+        let output = match day {
+            3 => day_03::tools(&args[2..]),
+            4 => day_04::tools(&args[2..]),
+            5 => day_05::tools(&args[2..]),
+            6 => day_06::tools(&args[2..]),
+            7 => day_07::tools(&args[2..]),
+            8 => day_08::tools(&args[2..]),
+            9 => day_09::tools(&args[2..]),
+            10 => day_10::tools(&args[2..]),
+            11 => day_11::tools(&args[2..]),
+            12 => day_12::tools(&args[2..]),
+            13 => day_13::tools(&args[2..]),
+            14 => day_14::tools(&args[2..]),
+            _ => format!("Day {} has no extra commands", day),
+        };
+        println!("{}", output);
+        return;
+    }
 
     let answer = match day {
         1 => format!("P1:\n{}\n\nP2:\n{}", day_01::p1(), day_01::p2()),
@@ -0,0 +1,31 @@
+use std::fs;
+
+// Reads the file a command was pointed at, or uses the puzzle input when it wasn't given one
+pub fn read_input(path: Option<&String>, default: &str) -> Result<String, String> {
+    match path {
+        Some(path) => fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err)),
+        None => Ok(default.to_string()),
+    }
+}
+
+// The arguments `tools` would get from the command line, for tests
+#[cfg(test)]
+pub fn args(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_input_or_default() {
+        assert_eq!(Ok("default".to_string()), read_input(None, "default"));
+        assert!(read_input(Some(&"Cargo.toml".to_string()), "default")
+            .unwrap()
+            .contains("[package]"));
+
+        let err = read_input(Some(&"no/such/file".to_string()), "default").unwrap_err();
+        assert!(err.starts_with("no/such/file: "));
+    }
+}